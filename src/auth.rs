@@ -0,0 +1,104 @@
+use crate::{ErrorCode, ListError};
+
+/// How much a game server is trusted, decided by the token it registered with
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Trust {
+    Official,
+    Trusted,
+    Community,
+    /// Authentication is disabled, so only the official ranges decide
+    Unchecked,
+}
+
+/// Checks the tokens game servers register with
+///
+/// When no tokens are configured authentication is disabled and every game server is let in.
+#[derive(Debug, Clone, Default)]
+pub struct Auth {
+    tokens: Vec<String>,
+    official_tokens: Vec<String>,
+    required: bool,
+}
+
+impl Auth {
+    pub fn new(tokens: Vec<String>, official_tokens: Vec<String>, required: bool) -> Auth {
+        Auth {
+            tokens,
+            official_tokens,
+            required,
+        }
+    }
+    pub fn enabled(&self) -> bool {
+        !self.tokens.is_empty() || !self.official_tokens.is_empty()
+    }
+    /// Returns how much to trust a game server presenting the token, or an error when it must be
+    /// rejected
+    pub fn check(&self, token: Option<&str>) -> Result<Trust, ListError> {
+        if !self.enabled() {
+            return Ok(Trust::Unchecked);
+        }
+        match token {
+            Some(token) if contains(&self.official_tokens, token) => Ok(Trust::Official),
+            Some(token) if contains(&self.tokens, token) => Ok(Trust::Trusted),
+            Some(_) => Err(ListError::new(ErrorCode::Unauthorized, "invalid token")),
+            None if self.required => Err(ListError::new(
+                ErrorCode::Unauthorized,
+                "a token is required to register",
+            )),
+            None => Ok(Trust::Community),
+        }
+    }
+}
+
+fn contains(tokens: &[String], token: &str) -> bool {
+    // check every token so the time taken doesn't leak which one matched
+    tokens
+        .iter()
+        .fold(false, |found, valid| constant_time_eq(valid, token) | found)
+}
+
+pub fn constant_time_eq(a: &str, b: &str) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.bytes()
+        .zip(b.bytes())
+        .fold(0, |diff, (a, b)| diff | (a ^ b))
+        == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn auth(required: bool) -> Auth {
+        Auth::new(
+            vec![String::from("trusted")],
+            vec![String::from("official")],
+            required,
+        )
+    }
+
+    #[test]
+    fn disabled_lets_everyone_in() {
+        let auth = Auth::default();
+        assert_eq!(auth.check(None), Ok(Trust::Unchecked));
+        assert_eq!(auth.check(Some("anything")), Ok(Trust::Unchecked));
+    }
+
+    #[test]
+    fn check_tokens() {
+        let auth = auth(false);
+        assert_eq!(auth.check(Some("official")), Ok(Trust::Official));
+        assert_eq!(auth.check(Some("trusted")), Ok(Trust::Trusted));
+        assert_eq!(auth.check(None), Ok(Trust::Community));
+        assert!(auth.check(Some("wrong")).is_err());
+    }
+
+    #[test]
+    fn require_token() {
+        let auth = auth(true);
+        assert_eq!(auth.check(Some("trusted")), Ok(Trust::Trusted));
+        assert!(auth.check(None).is_err());
+    }
+}
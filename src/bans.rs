@@ -0,0 +1,282 @@
+use crate::{json_file, names::normalize, unix_timestamp};
+use ipnet::IpNet;
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    io,
+    net::IpAddr,
+    path::Path,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+use uuid::Uuid;
+
+/// The longest ban with a duration, anything longer should be left to never end
+pub const MAX_DURATION: Duration = Duration::from_secs(100 * 365 * 24 * 60 * 60);
+
+/// A case-insensitive regex matched against game server names
+#[derive(Debug, Clone)]
+pub struct NamePattern(Regex);
+
+impl NamePattern {
+    pub fn new(pattern: &str) -> Result<NamePattern, regex::Error> {
+        regex::RegexBuilder::new(pattern)
+            .case_insensitive(true)
+            .build()
+            .map(NamePattern)
+    }
+    pub fn is_match(&self, name: &str) -> bool {
+        self.0.is_match(name)
+    }
+}
+
+impl PartialEq for NamePattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+impl Serialize for NamePattern {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.0.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for NamePattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        NamePattern::new(&pattern).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Which game servers a ban applies to
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BanTarget {
+    /// Game servers connecting from the IP
+    Ip(IpAddr),
+    /// Game servers connecting from anywhere in the range
+    Cidr(IpNet),
+    /// Game servers with exactly this name, ignoring case
+    Name(String),
+    /// Game servers with a name matching the pattern
+    NamePattern(NamePattern),
+}
+
+impl BanTarget {
+    pub fn matches(&self, ip: IpAddr, name: &str) -> bool {
+        match self {
+            BanTarget::Ip(banned) => banned.to_canonical() == ip.to_canonical(),
+            BanTarget::Cidr(range) => range.contains(&ip.to_canonical()),
+            // names are normalised before listing, so the ban text has to be too
+            BanTarget::Name(banned) => {
+                normalize(banned).to_lowercase() == normalize(name).to_lowercase()
+            }
+            BanTarget::NamePattern(pattern) => pattern.is_match(name),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Ban {
+    /// Generated for bans written by hand without one
+    #[serde(default = "Uuid::new_v4")]
+    pub id: Uuid,
+    #[serde(flatten)]
+    pub target: BanTarget,
+    /// Unix timestamp in seconds the ban ends at, it never ends when unset
+    pub expires_at: Option<u64>,
+    /// Told to the banned game server when it's rejected
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+impl Ban {
+    /// Describes the ban to the game server it rejects
+    pub fn message(&self) -> String {
+        match &self.reason {
+            Some(reason) => format!("banned from the list: {}", reason),
+            None => String::from("banned from the list"),
+        }
+    }
+    fn is_active(&self, now: u64) -> bool {
+        self.expires_at.is_none_or(|expires_at| now < expires_at)
+    }
+}
+
+/// Game servers which aren't allowed to register, shared between clones
+#[derive(Debug, Clone, Default)]
+pub struct BanList {
+    bans: Arc<RwLock<Vec<Ban>>>,
+}
+
+impl BanList {
+    pub fn new() -> BanList {
+        BanList::default()
+    }
+    pub fn with_bans(bans: Vec<Ban>) -> BanList {
+        BanList {
+            bans: Arc::new(RwLock::new(bans)),
+        }
+    }
+    /// Bans the target for the duration, or forever without one
+    pub fn add(
+        &self,
+        target: BanTarget,
+        duration: Option<Duration>,
+        reason: Option<String>,
+    ) -> Ban {
+        let now = unix_timestamp();
+        let ban = Ban {
+            id: Uuid::new_v4(),
+            target,
+            expires_at: duration.map(|duration| now.saturating_add(duration.as_secs())),
+            reason,
+        };
+        let mut bans = self.bans.write().unwrap();
+        bans.retain(|ban| ban.is_active(now));
+        bans.push(ban.clone());
+        ban
+    }
+    pub fn remove(&self, ban_id: &Uuid) -> Option<Ban> {
+        let mut bans = self.bans.write().unwrap();
+        let index = bans.iter().position(|ban| ban.id == *ban_id)?;
+        Some(bans.remove(index))
+    }
+    /// Returns the bans which haven't expired yet
+    pub fn active(&self) -> Vec<Ban> {
+        let now = unix_timestamp();
+        let bans = self.bans.read().unwrap();
+        bans.iter()
+            .filter(|ban| ban.is_active(now))
+            .cloned()
+            .collect()
+    }
+    /// Returns the active ban a game server falls under, if any
+    pub fn find(&self, ip: IpAddr, name: &str) -> Option<Ban> {
+        let now = unix_timestamp();
+        let bans = self.bans.read().unwrap();
+        bans.iter()
+            .find(|ban| ban.is_active(now) && ban.target.matches(ip, name))
+            .cloned()
+    }
+    /// Returns the active IP or CIDR ban covering the IP, so connections can be rejected before the
+    /// game server's name is known
+    pub fn find_ip(&self, ip: IpAddr) -> Option<Ban> {
+        let now = unix_timestamp();
+        let bans = self.bans.read().unwrap();
+        bans.iter()
+            .find(|ban| {
+                ban.is_active(now)
+                    && matches!(ban.target, BanTarget::Ip(_) | BanTarget::Cidr(_))
+                    && ban.target.matches(ip, "")
+            })
+            .cloned()
+    }
+}
+
+/// Writes the bans as indented JSON so they're easy to edit by hand
+pub async fn save(path: &Path, bans: &[Ban]) -> io::Result<()> {
+    json_file::save(path, bans, true).await
+}
+
+/// Reads bans saved by [`save`] or written by hand, returning an empty list if there's no file yet
+pub async fn load(path: &Path) -> io::Result<Vec<Ban>> {
+    Ok(json_file::load(path).await?.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    const IP: IpAddr = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7));
+
+    #[test]
+    fn ban_ip_and_name() {
+        let bans = BanList::new();
+        assert_eq!(bans.find(IP, "Griefers United"), None);
+        let ip_ban = bans.add(BanTarget::Ip(IP), None, None);
+        assert_eq!(bans.find(IP, "Anything"), Some(ip_ban.clone()));
+        let ipv6: IpAddr = "::ffff:203.0.113.7".parse().unwrap();
+        assert!(bans.find(ipv6, "Anything").is_some());
+
+        let pattern = NamePattern::new("^griefers").unwrap();
+        bans.add(BanTarget::NamePattern(pattern), None, None);
+        let other_ip = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 8));
+        assert!(bans.find(other_ip, "Griefers United").is_some());
+        assert!(bans.find(other_ip, "Not Griefers").is_none());
+
+        assert_eq!(bans.remove(&ip_ban.id), Some(ip_ban));
+        assert!(bans.find(IP, "Anything").is_none());
+        assert_eq!(bans.active().len(), 1);
+    }
+
+    #[test]
+    fn ban_range_and_exact_name() {
+        let bans = BanList::new();
+        bans.add(
+            BanTarget::Cidr("203.0.113.0/24".parse().unwrap()),
+            None,
+            None,
+        );
+        bans.add(BanTarget::Name(String::from("Spam Server")), None, None);
+        assert!(bans.find_ip(IP).is_some());
+        let other_ip = IpAddr::V4(Ipv4Addr::new(198, 51, 100, 1));
+        assert!(bans.find_ip(other_ip).is_none());
+        assert!(bans.find(other_ip, "spam server").is_some());
+        assert!(bans.find(other_ip, "Spam Server 2").is_none());
+        bans.add(
+            BanTarget::Name(String::from(" Ｆｕｌｌ  Width ")),
+            None,
+            None,
+        );
+        assert!(bans.find(other_ip, "full width").is_some());
+    }
+
+    #[test]
+    fn long_bans_dont_overflow() {
+        let bans = BanList::new();
+        let ban = bans.add(BanTarget::Ip(IP), Some(Duration::from_secs(u64::MAX)), None);
+        assert_eq!(ban.expires_at, Some(u64::MAX));
+        assert!(bans.find_ip(IP).is_some());
+    }
+
+    #[test]
+    fn bans_expire() {
+        let bans = BanList::new();
+        bans.add(BanTarget::Ip(IP), Some(Duration::ZERO), None);
+        assert!(bans.find(IP, "Test").is_none());
+        assert!(bans.active().is_empty());
+    }
+
+    #[test]
+    fn serialize_ban() {
+        let ban: Ban = serde_json::from_str(
+            r#"{"id":"00000000-0000-0000-0000-000000000000","name_pattern":"^bad","expires_at":null}"#,
+        )
+        .unwrap();
+        assert!(ban.target.matches(IP, "BAD server"));
+        let json = serde_json::to_string(&ban).unwrap();
+        assert!(json.contains(r#""name_pattern":"^bad""#));
+        assert!(serde_json::from_str::<BanTarget>(r#"{"name_pattern":"("}"#).is_err());
+        assert_eq!(ban.message(), "banned from the list");
+        let ban: Ban = serde_json::from_str(r#"{"ip":"203.0.113.7","expires_at":null}"#).unwrap();
+        assert!(!ban.id.is_nil());
+    }
+
+    #[tokio::test]
+    async fn save_and_load() {
+        let path = std::env::temp_dir().join(format!("bans-{}.json", Uuid::new_v4()));
+        assert_eq!(load(&path).await.unwrap(), Vec::new());
+
+        let bans = BanList::new();
+        let reason = Some(String::from("cheating"));
+        let ban = bans.add(BanTarget::Ip(IP), Some(Duration::from_secs(60)), reason);
+        assert_eq!(ban.message(), "banned from the list: cheating");
+        save(&path, &bans.active()).await.unwrap();
+        let loaded = BanList::with_bans(load(&path).await.unwrap());
+        assert_eq!(loaded.find(IP, "Test"), Some(ban));
+        tokio::fs::remove_file(&path).await.unwrap();
+    }
+}
@@ -0,0 +1,174 @@
+use crate::{auth::constant_time_eq, GameServer, ServerList};
+use hyper::{body, client::HttpConnector, header, Body, Client, Request, Uri};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// The game servers connected to one instance of the list, exchanged between peers
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct PeerList {
+    pub instance: String,
+    pub servers: Vec<GameServer>,
+}
+
+/// This instance's identity and the token peers must present to read its game servers
+#[derive(Debug, Clone)]
+pub struct Federation {
+    instance: String,
+    token: String,
+}
+
+impl Federation {
+    pub fn new(instance: String, token: String) -> Federation {
+        Federation { instance, token }
+    }
+    pub fn instance(&self) -> &str {
+        &self.instance
+    }
+    pub fn authorize(&self, token: Option<&str>) -> bool {
+        token.is_some_and(|token| constant_time_eq(&self.token, token))
+    }
+}
+
+/// Another instance of the list to fetch game servers from
+#[derive(Debug, Clone)]
+pub struct Peer {
+    /// Base URL of the peer, e.g. `http://list-2:3000`
+    pub url: String,
+    /// Game to fetch servers for
+    pub game: String,
+    pub token: String,
+    /// How often to fetch the peer's game servers
+    pub interval: Duration,
+    /// How long the peer can go without answering before its game servers are removed
+    pub timeout: Duration,
+}
+
+impl Peer {
+    /// Fetches the game servers connected to the peer
+    pub async fn fetch(&self, client: &Client<HttpConnector>) -> Result<PeerList, String> {
+        let url = self.url.trim_end_matches('/');
+        let uri = format!("{}/internal/{}/servers", url, self.game)
+            .parse::<Uri>()
+            .map_err(|e| e.to_string())?;
+        let request = Request::get(uri)
+            .header(header::AUTHORIZATION, format!("Bearer {}", self.token))
+            .body(Body::empty())
+            .map_err(|e| e.to_string())?;
+        let response = client.request(request).await.map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("peer responded with {}", response.status()));
+        }
+        let bytes = body::to_bytes(response.into_body())
+            .await
+            .map_err(|e| e.to_string())?;
+        serde_json::from_slice(&bytes).map_err(|e| e.to_string())
+    }
+}
+
+/// Keeps the peer's game servers in the list, removing them once it's been silent for too long
+pub async fn follow_peer(mut server_list: ServerList, peer: Peer, own_instance: String) {
+    let client = Client::new();
+    let mut interval = tokio::time::interval(peer.interval);
+    let mut instance: Option<String> = None;
+    let mut last_synced = Instant::now();
+    loop {
+        interval.tick().await;
+        let fetched = tokio::time::timeout(peer.timeout, peer.fetch(&client))
+            .await
+            .unwrap_or_else(|_| Err(String::from("timed out")));
+        match fetched {
+            Ok(list) if list.instance == own_instance => {
+                tracing::warn!("peer {} is this instance, ignoring it", peer.url);
+            }
+            Ok(list) => {
+                if let Some(previous) = instance.take().filter(|i| *i != list.instance) {
+                    server_list.remove_peer(&previous);
+                }
+                tracing::debug!(
+                    "fetched {} game servers from {}",
+                    list.servers.len(),
+                    list.instance
+                );
+                server_list.sync_peer(&list.instance, list.servers);
+                instance = Some(list.instance);
+                last_synced = Instant::now();
+            }
+            Err(e) => {
+                tracing::warn!("failed to fetch game servers from {}: {}", peer.url, e);
+                if last_synced.elapsed() >= peer.timeout {
+                    if let Some(silent) = instance.take() {
+                        let removed = server_list.remove_peer(&silent);
+                        tracing::warn!(
+                            "removed {} game servers from silent peer {}",
+                            removed,
+                            silent
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_server, ServerQuery};
+    use axum::{routing::get, Json, Router};
+    use std::net::{SocketAddr, TcpListener};
+
+    #[test]
+    fn authorize_token() {
+        let federation = Federation::new(String::from("eu-1"), String::from("secret"));
+        assert!(federation.authorize(Some("secret")));
+        assert!(!federation.authorize(Some("wrong")));
+        assert!(!federation.authorize(None));
+    }
+
+    #[tokio::test]
+    async fn fetch_and_follow_peer() {
+        let mut remote = ServerList::new();
+        remote.add(test_server("Remote", 0, false));
+        let list = PeerList {
+            instance: String::from("eu-2"),
+            servers: remote.local(),
+        };
+        let app = Router::new().route(
+            "/internal/race/servers",
+            get(move || {
+                let list = list.clone();
+                async move { Json(list) }
+            }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr: SocketAddr = listener.local_addr().unwrap();
+        tokio::spawn(
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(app.into_make_service()),
+        );
+
+        let peer = Peer {
+            url: format!("http://{}/", addr),
+            game: String::from("race"),
+            token: String::from("secret"),
+            interval: Duration::from_millis(10),
+            timeout: Duration::from_secs(1),
+        };
+        let fetched = peer.fetch(&Client::new()).await.unwrap();
+        assert_eq!(fetched.instance, "eu-2");
+
+        let mut server_list = ServerList::new();
+        server_list.add(test_server("Local", 0, false));
+        let task = tokio::spawn(follow_peer(server_list.clone(), peer, String::from("eu-1")));
+        while server_list.len() < 2 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        task.abort();
+        let servers = server_list.get(&ServerQuery::default());
+        assert!(servers
+            .iter()
+            .any(|server| server.name == "Remote" && server.origin() == Some("eu-2")));
+        assert_eq!(server_list.local().len(), 1);
+    }
+}
@@ -0,0 +1,281 @@
+use crate::{
+    auth::Auth,
+    limit::{IpCounter, IpRateLimiter},
+    version::{deserialize_version, parse_version, VersionRule},
+    ErrorCode, GameServer, ListError, ServerList,
+};
+use semver::Version;
+use serde::Deserialize;
+use std::{collections::HashMap, sync::Arc};
+
+/// The game served on the routes without a game in their path
+pub const DEFAULT_GAME: &str = "default";
+
+/// Messages or requests allowed in a burst when a rate limit is set without a burst
+pub const DEFAULT_BURST: u32 = 10;
+
+/// Settings for one game, anything left unset falls back to the server list's own config
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct GameConfig {
+    pub auth_tokens: Option<Vec<String>>,
+    pub official_tokens: Option<Vec<String>>,
+    pub auth_required: Option<bool>,
+    pub max_servers_per_ip: Option<usize>,
+    pub game_message_rate: Option<f64>,
+    pub game_message_burst: Option<u32>,
+    pub list_request_rate: Option<f64>,
+    pub list_request_burst: Option<u32>,
+    /// Game versions allowed to register, any version is allowed when empty
+    #[serde(default)]
+    pub allowed_versions: Vec<String>,
+    /// Which game servers are listed for a client's version
+    pub version_rule: Option<VersionRule>,
+    /// Game servers older than this are rejected
+    #[serde(default, deserialize_with = "deserialize_version")]
+    pub min_version: Option<Version>,
+}
+
+impl GameConfig {
+    /// Fills in anything unset from the defaults
+    pub fn or(self, defaults: &GameConfig) -> GameConfig {
+        GameConfig {
+            auth_tokens: self.auth_tokens.or_else(|| defaults.auth_tokens.clone()),
+            official_tokens: self
+                .official_tokens
+                .or_else(|| defaults.official_tokens.clone()),
+            auth_required: self.auth_required.or(defaults.auth_required),
+            max_servers_per_ip: self.max_servers_per_ip.or(defaults.max_servers_per_ip),
+            game_message_rate: self.game_message_rate.or(defaults.game_message_rate),
+            game_message_burst: self.game_message_burst.or(defaults.game_message_burst),
+            list_request_rate: self.list_request_rate.or(defaults.list_request_rate),
+            list_request_burst: self.list_request_burst.or(defaults.list_request_burst),
+            allowed_versions: if self.allowed_versions.is_empty() {
+                defaults.allowed_versions.clone()
+            } else {
+                self.allowed_versions
+            },
+            version_rule: self.version_rule.or(defaults.version_rule),
+            min_version: self.min_version.or_else(|| defaults.min_version.clone()),
+        }
+    }
+}
+
+/// Parses the JSON object of game names to their config, checking the names can be used in paths
+pub fn parse_games(json: &str) -> Result<HashMap<String, GameConfig>, String> {
+    let games: HashMap<String, GameConfig> =
+        serde_json::from_str(json).map_err(|e| e.to_string())?;
+    for name in games.keys() {
+        if !is_valid_name(name) {
+            return Err(format!(
+                "{:?} is not a valid game name, use lowercase letters, digits, - and _",
+                name
+            ));
+        }
+    }
+    Ok(games)
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-' || b == b'_')
+}
+
+/// A game's own list of servers along with the rules for registering to it
+#[derive(Clone)]
+pub struct Game {
+    pub name: String,
+    pub server_list: ServerList,
+    pub auth: Arc<Auth>,
+    pub servers_per_ip: IpCounter,
+    pub max_servers_per_ip: Option<usize>,
+    /// Rate and burst for each game server's messages
+    pub game_message_limit: Option<(f64, u32)>,
+    pub list_request_limiter: Option<IpRateLimiter>,
+    pub allowed_versions: Vec<String>,
+    pub min_version: Option<Version>,
+}
+
+impl Game {
+    /// Creates the game from its own config, falling back to the server list's defaults
+    pub fn new(
+        name: String,
+        server_list: ServerList,
+        config: &GameConfig,
+        defaults: &GameConfig,
+    ) -> Game {
+        let config = config.clone().or(defaults);
+        let burst = |burst: Option<u32>| burst.unwrap_or(DEFAULT_BURST);
+        Game {
+            name,
+            server_list: server_list.with_version_rule(config.version_rule.unwrap_or_default()),
+            auth: Arc::new(Auth::new(
+                config.auth_tokens.clone().unwrap_or_default(),
+                config.official_tokens.clone().unwrap_or_default(),
+                config.auth_required.unwrap_or_default(),
+            )),
+            servers_per_ip: IpCounter::new(),
+            max_servers_per_ip: config.max_servers_per_ip,
+            game_message_limit: config
+                .game_message_rate
+                .map(|rate| (rate, burst(config.game_message_burst))),
+            list_request_limiter: config
+                .list_request_rate
+                .map(|rate| IpRateLimiter::new(rate, burst(config.list_request_burst))),
+            allowed_versions: config.allowed_versions.clone(),
+            min_version: config.min_version.clone(),
+        }
+    }
+    /// Rejects game servers running a version the game doesn't allow
+    pub fn check_version(&self, server: &GameServer) -> Result<(), ListError> {
+        if let Some(min_version) = &self.min_version {
+            let version = server.metadata.game_version.as_deref();
+            match version.and_then(parse_version) {
+                Some(parsed) if parsed >= *min_version => {}
+                Some(_) => {
+                    return Err(ListError::new(
+                        ErrorCode::UnsupportedVersion,
+                        format!(
+                            "game version {} is older than the minimum {}",
+                            version.unwrap_or_default(),
+                            min_version
+                        ),
+                    ))
+                }
+                None => {
+                    return Err(ListError::new(
+                        ErrorCode::UnsupportedVersion,
+                        format!("a game_version of at least {} is required", min_version),
+                    ))
+                }
+            }
+        }
+        if self.allowed_versions.is_empty() {
+            return Ok(());
+        }
+        match server.metadata.game_version.as_deref() {
+            Some(version) if self.allowed_versions.iter().any(|v| v == version) => Ok(()),
+            Some(version) => Err(ListError::new(
+                ErrorCode::UnsupportedVersion,
+                format!("game version {} is not allowed", version),
+            )),
+            None => Err(ListError::new(
+                ErrorCode::UnsupportedVersion,
+                "a game_version is required to register",
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_server, ServerMetadata};
+
+    fn server(version: Option<&str>) -> GameServer {
+        test_server("Test", 0, false).with_metadata(ServerMetadata {
+            game_version: version.map(String::from),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn parse_game_config() {
+        let games = parse_games(
+            r#"{"race": {"auth_tokens": ["secret"], "allowed_versions": ["1.0"]}, "kart": {}}"#,
+        )
+        .unwrap();
+        assert_eq!(games.len(), 2);
+        assert_eq!(
+            games["race"].auth_tokens,
+            Some(vec![String::from("secret")])
+        );
+        assert!(parse_games(r#"{"Bad Name": {}}"#).is_err());
+        assert!(parse_games(r#"{"race": {"unknown": 1}}"#).is_err());
+    }
+
+    #[test]
+    fn fall_back_to_defaults() {
+        let defaults = GameConfig {
+            auth_tokens: Some(vec![String::from("global")]),
+            max_servers_per_ip: Some(5),
+            ..Default::default()
+        };
+        let config = GameConfig {
+            max_servers_per_ip: Some(1),
+            ..Default::default()
+        }
+        .or(&defaults);
+        assert_eq!(config.auth_tokens, defaults.auth_tokens);
+        assert_eq!(config.max_servers_per_ip, Some(1));
+    }
+
+    #[test]
+    fn check_allowed_versions() {
+        let config = GameConfig {
+            allowed_versions: vec![String::from("1.2.0")],
+            ..Default::default()
+        };
+        let game = Game::new(
+            String::from("race"),
+            ServerList::new(),
+            &config,
+            &GameConfig::default(),
+        );
+        assert!(game.check_version(&server(Some("1.2.0"))).is_ok());
+        assert!(game.check_version(&server(Some("1.1.0"))).is_err());
+        assert!(game.check_version(&server(None)).is_err());
+    }
+
+    #[test]
+    fn check_min_version() {
+        let games = parse_games(r#"{"race": {"min_version": "1.2"}}"#).unwrap();
+        let game = Game::new(
+            String::from("race"),
+            ServerList::new(),
+            &games["race"],
+            &GameConfig::default(),
+        );
+        assert!(game.check_version(&server(Some("1.2.0"))).is_ok());
+        assert!(game.check_version(&server(Some("v1.10"))).is_ok());
+        assert!(game.check_version(&server(Some("1.1.9"))).is_err());
+        assert!(game.check_version(&server(Some("nightly"))).is_err());
+        assert!(game.check_version(&server(None)).is_err());
+        assert!(parse_games(r#"{"race": {"min_version": "latest"}}"#).is_err());
+    }
+
+    #[test]
+    fn limit_with_defaults() {
+        let defaults = GameConfig {
+            game_message_rate: Some(5.0),
+            game_message_burst: Some(20),
+            ..Default::default()
+        };
+        let config = GameConfig {
+            game_message_burst: Some(2),
+            ..Default::default()
+        };
+        let game = Game::new(String::from("race"), ServerList::new(), &config, &defaults);
+        assert_eq!(game.game_message_limit, Some((5.0, 2)));
+        let game = Game::new(
+            String::from("race"),
+            ServerList::new(),
+            &GameConfig::default(),
+            &defaults,
+        );
+        assert_eq!(game.game_message_limit, Some((5.0, 20)));
+        let defaults = GameConfig {
+            game_message_rate: Some(5.0),
+            ..Default::default()
+        };
+        let game = Game::new(
+            String::from("race"),
+            ServerList::new(),
+            &GameConfig::default(),
+            &defaults,
+        );
+        assert_eq!(game.game_message_limit, Some((5.0, DEFAULT_BURST)));
+    }
+}
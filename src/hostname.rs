@@ -0,0 +1,211 @@
+use crate::{ErrorCode, ListError};
+use ipnet::IpNet;
+use serde::{Deserialize, Deserializer};
+use std::{future::Future, io, net::IpAddr, time::Duration};
+
+// resolving a hostname shouldn't hold up registration for long
+const RESOLVE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Looks up the addresses a hostname points to
+pub trait Resolver {
+    fn resolve(&self, hostname: &str) -> impl Future<Output = io::Result<Vec<IpAddr>>> + Send;
+}
+
+/// Resolves hostnames with the operating system's resolver
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemResolver;
+
+impl Resolver for SystemResolver {
+    async fn resolve(&self, hostname: &str) -> io::Result<Vec<IpAddr>> {
+        let addrs = tokio::net::lookup_host((hostname, 0)).await?;
+        Ok(addrs.map(|addr| addr.ip()).collect())
+    }
+}
+
+/// Allows hostnames matching `pattern` for game servers in `range` without resolving them, the
+/// pattern is either an exact hostname or `*.` followed by a domain to allow any subdomain
+#[derive(Debug, Clone, PartialEq)]
+pub struct AllowedHostname {
+    pattern: String,
+    range: IpNet,
+}
+
+impl AllowedHostname {
+    fn matches(&self, hostname: &str, ips: &[IpAddr]) -> bool {
+        let name_matches = match self.pattern.strip_prefix("*.") {
+            Some(domain) => hostname
+                .strip_suffix(domain)
+                .is_some_and(|subdomain| subdomain.ends_with('.') && subdomain.len() > 1),
+            None => hostname == self.pattern,
+        };
+        name_matches && ips.iter().any(|ip| self.range.contains(&ip.to_canonical()))
+    }
+}
+
+impl std::str::FromStr for AllowedHostname {
+    type Err = String;
+
+    fn from_str(entry: &str) -> Result<Self, Self::Err> {
+        let (pattern, range) = entry
+            .split_once('=')
+            .ok_or_else(|| format!("expected hostname=cidr but got {:?}", entry))?;
+        Ok(AllowedHostname {
+            pattern: pattern.trim().to_ascii_lowercase(),
+            range: range
+                .trim()
+                .parse()
+                .map_err(|e| format!("{}: {:?}", e, range))?,
+        })
+    }
+}
+
+/// Deserializes a list of `hostname=cidr` entries, skipping empty entries
+pub fn deserialize_allowlist<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<AllowedHostname>, D::Error> {
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|entry| entry.trim())
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| entry.parse().map_err(serde::de::Error::custom))
+        .collect()
+}
+
+/// Checks game servers only advertise hostnames which point to them
+#[derive(Debug, Clone, Default)]
+pub struct HostnameVerifier<R> {
+    resolver: R,
+    allowlist: Vec<AllowedHostname>,
+}
+
+impl<R: Resolver> HostnameVerifier<R> {
+    pub fn new(resolver: R, allowlist: Vec<AllowedHostname>) -> HostnameVerifier<R> {
+        HostnameVerifier {
+            resolver,
+            allowlist,
+        }
+    }
+    /// Returns the normalised hostname if it's allowlisted for or resolves to one of the IPs the
+    /// game server connected from or is listed under
+    pub async fn verify(&self, hostname: &str, ips: &[IpAddr]) -> Result<String, ListError> {
+        let hostname = hostname.trim_end_matches('.').to_ascii_lowercase();
+        if !is_valid_hostname(&hostname) {
+            return Err(ListError::new(
+                ErrorCode::InvalidHostname,
+                format!("{:?} is not a valid hostname", hostname),
+            ));
+        }
+        if self
+            .allowlist
+            .iter()
+            .any(|allowed| allowed.matches(&hostname, ips))
+        {
+            return Ok(hostname);
+        }
+        let resolved = tokio::time::timeout(RESOLVE_TIMEOUT, self.resolver.resolve(&hostname))
+            .await
+            .unwrap_or_else(|_| Err(io::ErrorKind::TimedOut.into()))
+            .map_err(|e| {
+                ListError::new(
+                    ErrorCode::InvalidHostname,
+                    format!("failed to resolve {}: {}", hostname, e),
+                )
+            })?;
+        let points_to_server = resolved.iter().any(|resolved| {
+            ips.iter()
+                .any(|ip| ip.to_canonical() == resolved.to_canonical())
+        });
+        if points_to_server {
+            Ok(hostname)
+        } else {
+            Err(ListError::new(
+                ErrorCode::InvalidHostname,
+                format!("{} does not resolve to the game server's IP", hostname),
+            ))
+        }
+    }
+}
+
+fn is_valid_hostname(hostname: &str) -> bool {
+    !hostname.is_empty()
+        && hostname.len() <= 253
+        && hostname.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    // stands in for DNS so tests don't need network access
+    #[derive(Default)]
+    struct StaticResolver(HashMap<String, Vec<IpAddr>>);
+
+    impl Resolver for StaticResolver {
+        async fn resolve(&self, hostname: &str) -> io::Result<Vec<IpAddr>> {
+            self.0
+                .get(hostname)
+                .cloned()
+                .ok_or_else(|| io::ErrorKind::NotFound.into())
+        }
+    }
+
+    fn verifier(allowlist: &[&str]) -> HostnameVerifier<StaticResolver> {
+        let mut hosts = HashMap::new();
+        hosts.insert(
+            String::from("game.example.com"),
+            vec!["203.0.113.7".parse().unwrap()],
+        );
+        let allowlist = allowlist
+            .iter()
+            .map(|entry| entry.parse().unwrap())
+            .collect();
+        HostnameVerifier::new(StaticResolver(hosts), allowlist)
+    }
+
+    #[tokio::test]
+    async fn resolves_to_server() {
+        let verifier = verifier(&[]);
+        let ip = "203.0.113.7".parse().unwrap();
+        assert_eq!(
+            verifier.verify("Game.Example.com.", &[ip]).await,
+            Ok(String::from("game.example.com"))
+        );
+        let other_ip = "203.0.113.8".parse().unwrap();
+        assert!(verifier
+            .verify("game.example.com", &[other_ip])
+            .await
+            .is_err());
+        assert!(verifier.verify("unknown.example.com", &[ip]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn allowlisted() {
+        let verifier = verifier(&["*.example.net=10.0.0.0/8"]);
+        let ip = "10.1.2.3".parse().unwrap();
+        assert!(verifier.verify("eu1.example.net", &[ip]).await.is_ok());
+        assert!(verifier.verify("example.net", &[ip]).await.is_err());
+        let other_ip = "192.168.1.1".parse().unwrap();
+        assert!(verifier
+            .verify("eu1.example.net", &[other_ip])
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn invalid_hostnames() {
+        let verifier = verifier(&[]);
+        let ip = "203.0.113.7".parse().unwrap();
+        for hostname in ["", "bad host", "-bad.example.com", "a..b"] {
+            assert!(verifier.verify(hostname, &[ip]).await.is_err());
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use serde::{de::DeserializeOwned, Serialize};
+use std::{io, path::Path};
+
+/// Writes the value as JSON, replacing the file in one go so a crash can't leave it half written
+pub async fn save<T: Serialize + ?Sized>(path: &Path, value: &T, pretty: bool) -> io::Result<()> {
+    let json = if pretty {
+        serde_json::to_vec_pretty(value)?
+    } else {
+        serde_json::to_vec(value)?
+    };
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    tokio::fs::write(&temp_path, json).await?;
+    tokio::fs::rename(&temp_path, path).await
+}
+
+/// Reads a value saved by [`save`] or written by hand, returning `None` if there's no file yet
+pub async fn load<T: DeserializeOwned>(path: &Path) -> io::Result<Option<T>> {
+    match tokio::fs::read(path).await {
+        Ok(json) => Ok(Some(serde_json::from_slice(&json)?)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn save_and_load() {
+        let path = std::env::temp_dir().join(format!("json-{}.json", uuid::Uuid::new_v4()));
+        assert_eq!(load::<Vec<u32>>(&path).await.unwrap(), None);
+        save(&path, &[1, 2, 3], true).await.unwrap();
+        assert_eq!(load(&path).await.unwrap(), Some(vec![1, 2, 3]));
+        tokio::fs::write(&path, "not json").await.unwrap();
+        assert!(load::<Vec<u32>>(&path).await.is_err());
+        tokio::fs::remove_file(&path).await.unwrap();
+    }
+}
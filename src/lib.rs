@@ -1,145 +1,1112 @@
+pub mod auth;
+pub mod bans;
+pub mod federation;
+pub mod games;
+pub mod hostname;
+pub mod json_file;
+pub mod limit;
+pub mod names;
+pub mod network;
+pub mod probe;
+pub mod quickjoin;
+pub mod reservation;
+pub mod snapshot;
+pub mod store;
+pub mod version;
+
+use auth::Trust;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use limit::IpSlot;
+use quickjoin::{QuickJoinQuery, ScoringPolicy};
 use serde::{Deserialize, Serialize};
+use snapshot::{SavedServer, Snapshot};
 use std::{
+    cmp::Ordering,
     collections::HashMap,
-    net::IpAddr,
-    sync::{Arc, RwLock},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
+use store::{MemoryStore, ServerStore, StoreError};
+use tokio::sync::broadcast;
 use uuid::Uuid;
+use version::VersionRule;
 
-#[derive(Debug, Serialize, Clone, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct GameServer {
+    id: Uuid,
     name: String,
     ip: IpAddr,
+    /// Every address the server can be reached on, `ip` is always one of them
+    ipv4: Option<Ipv4Addr>,
+    ipv6: Option<Ipv6Addr>,
+    /// Hostname matching the server's TLS certificate
+    hostname: Option<String>,
     tls: bool,
     port: u16,
     official: bool,
+    /// Registered without a token while authentication is enabled
+    community: bool,
     pub players: u32,
+    /// Slots the game server is holding for players with join tickets, they count as taken
+    #[serde(default)]
+    pub reserved: u32,
+    /// Unix timestamp in seconds of the last message from the game server, as of the last heartbeat
+    last_seen: u64,
+    /// Whether the last probe could connect to the server, unknown when probing is disabled
+    pub reachable: Option<bool>,
+    status: ServerStatus,
+    /// Instance of the list the game server is connected to, unset when it's this one
+    #[serde(default)]
+    origin: Option<String>,
+    /// Pinned by an admin so it's listed first
+    #[serde(default)]
+    featured: bool,
+    /// The name matched the moderation filter, for admins to review
+    #[serde(default)]
+    flagged: bool,
+    #[serde(flatten)]
+    pub metadata: ServerMetadata,
 }
 
 impl GameServer {
     pub fn new(name: String, ip: IpAddr, tls: bool, port: u16, official: bool) -> GameServer {
         GameServer {
+            id: Uuid::nil(),
             name,
             ip,
+            ipv4: None,
+            ipv6: None,
+            hostname: None,
             tls,
             port,
             official,
+            community: false,
             players: 0,
+            reserved: 0,
+            last_seen: 0,
+            reachable: None,
+            status: ServerStatus::Online,
+            origin: None,
+            featured: false,
+            flagged: false,
+            metadata: ServerMetadata::default(),
         }
+        .with_alternate_ip(ip)
+    }
+    /// Advertises another address the server can be reached on, unless one is already known for
+    /// that address family
+    pub fn with_alternate_ip(mut self, ip: IpAddr) -> GameServer {
+        match ip {
+            IpAddr::V4(ipv4) => self.ipv4 = self.ipv4.or(Some(ipv4)),
+            IpAddr::V6(ipv6) => self.ipv6 = self.ipv6.or(Some(ipv6)),
+        }
+        self
+    }
+    pub fn with_metadata(mut self, metadata: ServerMetadata) -> GameServer {
+        self.metadata = metadata;
+        self
+    }
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    /// Lists the server under another name, such as its name after moderation
+    pub fn with_name(mut self, name: String) -> GameServer {
+        self.name = name;
+        self
+    }
+    pub fn ip(&self) -> IpAddr {
+        self.ip
+    }
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+    pub fn hostname(&self) -> Option<&str> {
+        self.hostname.as_deref()
+    }
+    /// Advertises a hostname, which should already be verified to point to the server
+    pub fn with_hostname(mut self, hostname: String) -> GameServer {
+        self.hostname = Some(hostname);
+        self
+    }
+    pub fn ipv4(&self) -> Option<Ipv4Addr> {
+        self.ipv4
+    }
+    pub fn ipv6(&self) -> Option<Ipv6Addr> {
+        self.ipv6
+    }
+    pub fn official(&self) -> bool {
+        self.official
+    }
+    pub fn community(&self) -> bool {
+        self.community
+    }
+    pub fn status(&self) -> ServerStatus {
+        self.status
+    }
+    pub fn origin(&self) -> Option<&str> {
+        self.origin.as_deref()
+    }
+    pub fn featured(&self) -> bool {
+        self.featured
     }
+    pub fn flagged(&self) -> bool {
+        self.flagged
+    }
+    pub fn with_flagged(mut self, flagged: bool) -> GameServer {
+        self.flagged = flagged;
+        self
+    }
+    /// Whether every slot is taken by a player or held for one with a join ticket
+    pub fn is_full(&self) -> bool {
+        self.metadata
+            .max_players
+            .is_some_and(|max_players| self.players.saturating_add(self.reserved) >= max_players)
+    }
+    /// Servers are hidden from the list until they're known to be reachable
+    pub fn is_visible(&self) -> bool {
+        self.reachable != Some(false)
+    }
+    /// Whether the servers only differ in when they were last seen, which changes with every
+    /// message so isn't worth telling subscribers about
+    pub(crate) fn same_listing(&self, other: &GameServer) -> bool {
+        *self
+            == GameServer {
+                last_seen: self.last_seen,
+                ..other.clone()
+            }
+    }
+    /// Applies the trust granted by the server's token. With authentication enabled only an
+    /// official token makes the server official, even when it connects from an official range
+    pub fn with_trust(mut self, trust: Trust) -> GameServer {
+        if trust != Trust::Unchecked {
+            self.official = trust == Trust::Official;
+            self.community = trust == Trust::Community;
+        }
+        self
+    }
+}
+
+/// Whether the game server behind an entry is still connected to the list
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ServerStatus {
+    #[default]
+    Online,
+    /// Lost its connection but can still resume its entry until the grace period ends
+    Disconnected,
+    /// Restored from a snapshot and waiting for the game server to reconnect
+    Pending,
+}
+
+/// Details about the game being hosted, only known for game servers connecting with V3 or later
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct ServerMetadata {
+    pub max_players: Option<u32>,
+    pub map: Option<String>,
+    pub game_mode: Option<String>,
+    pub game_version: Option<String>,
+    pub password_protected: bool,
+    pub tags: Vec<String>,
+    /// Where the game server is hosted, such as `eu` or `us-east`
+    #[serde(default)]
+    pub region: Option<String>,
 }
 
 // IMPORTANT: Add new versions to the top so they take precedence when JSON is parsed
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum ConnectMessage {
-    V2 { name: String, port: u16, tls: bool },
-    V1 { name: String, port: u16 },
+    V3 {
+        name: String,
+        port: u16,
+        tls: bool,
+        max_players: u32,
+        #[serde(default)]
+        map: Option<String>,
+        #[serde(default)]
+        game_mode: Option<String>,
+        #[serde(default)]
+        game_version: Option<String>,
+        #[serde(default)]
+        password_protected: bool,
+        #[serde(default)]
+        tags: Vec<String>,
+        #[serde(default)]
+        region: Option<String>,
+    },
+    V2 {
+        name: String,
+        port: u16,
+        tls: bool,
+    },
+    V1 {
+        name: String,
+        port: u16,
+    },
+}
+
+/// Fields accepted alongside every version of [`ConnectMessage`]
+#[derive(Debug, Deserialize, Default)]
+pub struct ConnectOptions {
+    /// Authentication token, can also be sent in the `Authorization` header
+    pub token: Option<String>,
+    /// Hostname to advertise instead of only the IP, must resolve to the game server
+    pub hostname: Option<String>,
+    /// Token from a previous registration, to keep that entry's id if it's still in the list
+    pub resume_token: Option<String>,
+    /// Public address of the other family the game server can also be reached on, so dual-stack
+    /// servers can advertise both an IPv4 and an IPv6 address
+    pub alternate_ip: Option<IpAddr>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum GameMessage {
-    Status { players: u32 },
+    Status {
+        players: u32,
+    },
+    /// Answers a [`ListMessage::ReserveSlot`], or reports a player joined with their ticket
+    // must come before `Details`, which matches any object as all its fields are optional
+    Reservation {
+        reservation_id: Uuid,
+        status: ReservationStatus,
+        /// Why the reservation was rejected, passed on to the client
+        #[serde(default)]
+        reason: Option<String>,
+    },
+    /// Updates the map and/or game mode, at least one must be set
+    Details {
+        map: Option<String>,
+        game_mode: Option<String>,
+    },
+}
+
+/// Messages sent from the list back to a game server
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ListMessage {
+    /// Sent once the server has been added to the list
+    Registered {
+        id: Uuid,
+        public_ip: IpAddr,
+        official: bool,
+        community: bool,
+        /// Send this when reconnecting to keep the same id
+        resume_token: String,
+        /// Whether an existing entry was resumed rather than a new one created
+        resumed: bool,
+        /// The name the server is listed under, which can differ once normalised or masked
+        name: String,
+        /// Whether the name was flagged for admins to review
+        flagged: bool,
+    },
+    Error {
+        code: ErrorCode,
+        message: String,
+    },
+    /// Sent whenever probing finds the server has become reachable or unreachable
+    Reachability {
+        reachable: bool,
+    },
+    /// Asks the game server to hold a slot for a client until the ticket expires, it must answer
+    /// with a [`GameMessage::Reservation`]
+    ReserveSlot {
+        reservation_id: Uuid,
+        /// Unix timestamp in seconds the client's ticket expires at
+        expires_at: u64,
+    },
 }
 
+/// How a game server answered a reservation
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReservationStatus {
+    /// The slot is held until the ticket expires
+    Accepted,
+    Rejected,
+    /// The client joined with its ticket, so it now counts as a player instead
+    Claimed,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    InvalidConnectMessage,
+    InvalidGameMessage,
+    InvalidMessageType,
+    Unauthorized,
+    TooManyServers,
+    RateLimited,
+    InvalidHostname,
+    ShuttingDown,
+    UnsupportedVersion,
+    Banned,
+    InvalidName,
+}
+
+/// An error which is reported back to the game server that caused it
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListError {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl ListError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> ListError {
+        ListError {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for ListError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}: {}", self.code, self.message)
+    }
+}
+
+impl From<ListError> for ListMessage {
+    fn from(error: ListError) -> Self {
+        ListMessage::Error {
+            code: error.code,
+            message: error.message,
+        }
+    }
+}
+
+/// Changes to the server list sent to subscribed game clients
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerEvent {
+    /// The whole list, sent when subscribing or after missing events
+    Snapshot {
+        servers: Vec<GameServer>,
+    },
+    Added {
+        server: GameServer,
+    },
+    /// Also sent when a hidden server becomes visible, so it may not be in the subscriber's list
+    Updated {
+        server: GameServer,
+    },
+    Removed {
+        id: Uuid,
+    },
+}
+
+// how many events a slow subscriber can fall behind before it needs a new snapshot
+const EVENT_CAPACITY: usize = 256;
+
+/// A game server's claim on its entry in the list, returned by [`ServerList::connect`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Registration {
+    pub id: Uuid,
+    pub resume_token: String,
+    pub resumed: bool,
+    // distinguishes this connection from any that resumed the entry after it
+    connection: u64,
+}
+
+// the connection currently attached to a server's entry and how to resume it
+#[derive(Debug)]
+struct Session {
+    token: String,
+    connection: u64,
+    // unix timestamp in seconds
+    disconnected_at: Option<u64>,
+    // counts the entry towards its IP's limit until it's removed, even while disconnected
+    ip_slot: Option<Arc<IpSlot>>,
+}
+
+#[derive(Debug, Default)]
+struct Sessions {
+    by_server: HashMap<Uuid, Session>,
+    by_token: HashMap<String, Uuid>,
+    last_connection: u64,
+}
+
+// Servers are keyed by UUIDv7 so ordering them by id yields them in registration order
 #[derive(Clone)]
 pub struct ServerList {
-    servers: Arc<RwLock<HashMap<Uuid, GameServer>>>,
+    store: Arc<dyn ServerStore>,
+    // also held while changing the store, so changes and their events are never interleaved
+    sessions: Arc<Mutex<Sessions>>,
+    events: broadcast::Sender<ServerEvent>,
+    version_rule: VersionRule,
 }
 
 impl ServerList {
     pub fn new() -> ServerList {
+        ServerList::with_store(Arc::new(MemoryStore::new()))
+    }
+    pub fn with_store(store: Arc<dyn ServerStore>) -> ServerList {
+        let (events, _) = broadcast::channel(EVENT_CAPACITY);
+        let sessions = Arc::<Mutex<Sessions>>::default();
+        let (watched_sessions, watched_events) = (sessions.clone(), events.clone());
+        store.watch(Box::new(move |change| {
+            let _sessions = watched_sessions.lock().unwrap();
+            for event in change() {
+                send_event(&watched_events, event);
+            }
+        }));
         ServerList {
-            servers: Arc::default(),
+            store,
+            sessions,
+            events,
+            version_rule: VersionRule::default(),
         }
     }
+    /// Sets which game servers are listed for a query's `client_version`
+    pub fn with_version_rule(mut self, version_rule: VersionRule) -> ServerList {
+        self.version_rule = version_rule;
+        self
+    }
     pub fn add(&mut self, server: GameServer) -> Uuid {
-        let mut servers = self.servers.write().unwrap();
-        let mut server_id = Uuid::new_v4();
-        // just in case the UUIDv4 clashes with an existing one
+        let _sessions = self.sessions.lock().unwrap();
+        self.insert(server)
+    }
+    // must be called while holding the sessions lock
+    fn insert(&self, mut server: GameServer) -> Uuid {
+        let mut server_id = Uuid::now_v7();
+        // just in case the UUIDv7 clashes with an existing one
         loop {
-            if servers.contains_key(&server_id) {
-                server_id = Uuid::new_v4();
+            if logged(self.store.get(&server_id)).is_some() {
+                server_id = Uuid::now_v7();
             } else {
                 break;
             }
         }
-        servers.insert(server_id, server);
+        server.id = server_id;
+        server.last_seen = unix_timestamp();
+        logged(self.store.add(&server));
+        self.notify(ServerEvent::Added { server });
         server_id
     }
+    /// Adds the server with a token it can resume its entry with after disconnecting
+    ///
+    /// When the resume token matches an entry still in the list the server replaces it, keeping
+    /// its id, player count and reserved slots, and any connection already attached to it loses
+    /// its claim. A server waiting to be probed keeps the entry's reachability until it is.
+    pub fn connect(&mut self, mut server: GameServer, resume_token: Option<&str>) -> Registration {
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.last_connection += 1;
+        let connection = sessions.last_connection;
+        let resumed = resume_token
+            .and_then(|token| sessions.by_token.get(token))
+            .and_then(|server_id| logged(self.store.get(server_id)));
+        if let Some(previous) = resumed {
+            let server_id = previous.id;
+            server.id = server_id;
+            server.players = previous.players;
+            server.reserved = previous.reserved;
+            server.featured = previous.featured;
+            if server.reachable == Some(false) {
+                server.reachable = previous.reachable;
+            }
+            server.last_seen = unix_timestamp();
+            logged(self.store.add(&server));
+            let session = sessions
+                .by_server
+                .get_mut(&server_id)
+                .expect("every token has a session");
+            session.connection = connection;
+            session.disconnected_at = None;
+            let resume_token = session.token.clone();
+            self.notify(ServerEvent::Updated { server });
+            return Registration {
+                id: server_id,
+                resume_token,
+                resumed: true,
+                connection,
+            };
+        }
+        let server_id = self.insert(server);
+        let resume_token = Uuid::new_v4().simple().to_string();
+        sessions.by_token.insert(resume_token.clone(), server_id);
+        sessions.by_server.insert(
+            server_id,
+            Session {
+                token: resume_token.clone(),
+                connection,
+                disconnected_at: None,
+                ip_slot: None,
+            },
+        );
+        Registration {
+            id: server_id,
+            resume_token,
+            resumed: false,
+            connection,
+        }
+    }
+    /// Returns the slot counting the entry the resume token belongs to towards its IP's limit
+    pub fn ip_slot(&self, resume_token: &str) -> Option<Arc<IpSlot>> {
+        let sessions = self.sessions.lock().unwrap();
+        let server_id = sessions.by_token.get(resume_token)?;
+        sessions.by_server.get(server_id)?.ip_slot.clone()
+    }
+    /// Counts the server's entry towards the slot's IP until the entry is removed, replacing the
+    /// slot it held before being resumed
+    pub fn hold_ip_slot(&mut self, registration: &Registration, ip_slot: Arc<IpSlot>) {
+        let mut sessions = self.sessions.lock().unwrap();
+        if let Some(session) = sessions.by_server.get_mut(&registration.id) {
+            if session.connection == registration.connection {
+                session.ip_slot = Some(ip_slot);
+            }
+        }
+    }
+    /// Marks the server as disconnected so it can be resumed, returns false if another connection
+    /// has resumed it since
+    pub fn disconnect(&mut self, registration: &Registration) -> bool {
+        let mut sessions = self.sessions.lock().unwrap();
+        let session = match sessions.by_server.get_mut(&registration.id) {
+            Some(session) if session.connection == registration.connection => session,
+            _ => return false,
+        };
+        session.disconnected_at = Some(unix_timestamp());
+        let updated = self.store.update(&registration.id, &mut |server| {
+            server.status = ServerStatus::Disconnected;
+        });
+        if let Some(server) = logged(updated) {
+            self.notify(ServerEvent::Updated { server });
+        }
+        true
+    }
+    /// Removes servers which have been disconnected for longer than the grace period
+    pub fn remove_expired(&mut self, grace_period: Duration) -> Vec<GameServer> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let now = unix_timestamp();
+        let expired = sessions
+            .by_server
+            .iter()
+            .filter(|(_, session)| {
+                session
+                    .disconnected_at
+                    .is_some_and(|at| now.saturating_sub(at) >= grace_period.as_secs())
+            })
+            .map(|(server_id, _)| *server_id)
+            .collect::<Vec<_>>();
+        let mut removed = Vec::new();
+        for server_id in expired {
+            if let Some(session) = sessions.by_server.remove(&server_id) {
+                sessions.by_token.remove(&session.token);
+            }
+            if let Some(server) = logged(self.store.remove(&server_id)) {
+                self.notify(ServerEvent::Removed { id: server_id });
+                removed.push(server);
+            }
+        }
+        removed
+    }
+    /// Returns every server which can be resumed along with its resume token
+    pub fn snapshot(&self) -> Snapshot {
+        let sessions = self.sessions.lock().unwrap();
+        let servers = logged(self.store.all())
+            .into_iter()
+            .filter_map(|server| {
+                let session = sessions.by_server.get(&server.id)?;
+                Some(SavedServer {
+                    resume_token: session.token.clone(),
+                    server,
+                })
+            })
+            .collect();
+        Snapshot { servers }
+    }
+    /// Adds the servers from a snapshot as pending, they're removed like disconnected servers
+    /// unless resumed within the grace period
+    pub fn restore(&mut self, snapshot: Snapshot) -> Vec<GameServer> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let now = unix_timestamp();
+        let mut restored = Vec::new();
+        for SavedServer {
+            mut server,
+            resume_token,
+        } in snapshot.servers
+        {
+            if sessions.by_server.contains_key(&server.id)
+                || sessions.by_token.contains_key(&resume_token)
+            {
+                continue;
+            }
+            server.status = ServerStatus::Pending;
+            sessions.by_token.insert(resume_token.clone(), server.id);
+            sessions.by_server.insert(
+                server.id,
+                Session {
+                    token: resume_token,
+                    // connections are counted from 1 so no connection owns the session
+                    connection: 0,
+                    disconnected_at: Some(now),
+                    ip_slot: None,
+                },
+            );
+            logged(self.store.add(&server));
+            self.notify(ServerEvent::Added {
+                server: server.clone(),
+            });
+            restored.push(server);
+        }
+        restored
+    }
+    /// Replaces the servers listed from another instance of the list with the ones it has now
+    pub fn sync_peer(&mut self, origin: &str, servers: Vec<GameServer>) {
+        let _sessions = self.sessions.lock().unwrap();
+        let mut existing = logged(self.store.all())
+            .into_iter()
+            .map(|server| (server.id, server))
+            .collect::<HashMap<_, _>>();
+        for mut server in servers {
+            server.origin = Some(String::from(origin));
+            match existing.remove(&server.id) {
+                Some(current) if current.origin != server.origin => {
+                    // ids are unique so this is a misbehaving peer
+                    tracing::warn!("ignoring game server {} from {}", server.id, origin);
+                    existing.insert(current.id, current);
+                }
+                Some(current) if current == server => {}
+                // peers touch their servers on every message, which isn't worth telling anyone
+                Some(current) if current.same_listing(&server) => {
+                    logged(self.store.add(&server));
+                }
+                Some(_) => {
+                    logged(self.store.add(&server));
+                    self.notify(ServerEvent::Updated { server });
+                }
+                None => {
+                    logged(self.store.add(&server));
+                    self.notify(ServerEvent::Added { server });
+                }
+            }
+        }
+        // whatever the peer no longer has has gone
+        for server in existing.into_values() {
+            if server.origin.as_deref() == Some(origin) {
+                logged(self.store.remove(&server.id));
+                self.notify(ServerEvent::Removed { id: server.id });
+            }
+        }
+    }
+    /// Removes every server listed from another instance of the list, returning how many there were
+    pub fn remove_peer(&mut self, origin: &str) -> usize {
+        let count = logged(self.store.all())
+            .iter()
+            .filter(|server| server.origin.as_deref() == Some(origin))
+            .count();
+        self.sync_peer(origin, Vec::new());
+        count
+    }
+    /// Returns the servers connected to this instance of the list rather than listed from a peer
+    pub fn local(&self) -> Vec<GameServer> {
+        let mut servers = self.all();
+        servers.retain(|server| server.origin.is_none());
+        servers
+    }
+    /// Returns every server in registration order, including hidden ones
+    pub fn all(&self) -> Vec<GameServer> {
+        logged(self.store.all())
+    }
     pub fn remove(&mut self, server_id: &Uuid) -> Option<GameServer> {
-        let mut servers = self.servers.write().unwrap();
-        servers.remove(server_id)
+        let mut sessions = self.sessions.lock().unwrap();
+        if let Some(session) = sessions.by_server.remove(server_id) {
+            sessions.by_token.remove(&session.token);
+        }
+        let removed = logged(self.store.remove(server_id));
+        if removed.is_some() {
+            self.notify(ServerEvent::Removed { id: *server_id });
+        }
+        removed
+    }
+    /// Pins or unpins the server so it sorts first, returning false if it isn't in the list
+    pub fn set_featured(&self, server_id: &Uuid, featured: bool) -> bool {
+        let _sessions = self.sessions.lock().unwrap();
+        let updated = self.store.update(server_id, &mut |server| {
+            server.featured = featured;
+        });
+        match logged(updated) {
+            Some(server) => {
+                self.notify(ServerEvent::Updated { server });
+                true
+            }
+            None => false,
+        }
+    }
+    /// Picks the best server for a client to join right now, if any match what it's looking for
+    pub fn quick_join(&self, query: &QuickJoinQuery, policy: &ScoringPolicy) -> Option<GameServer> {
+        quickjoin::best(logged(self.store.all()), query, policy, self.version_rule)
+    }
+    pub fn server(&self, server_id: &Uuid) -> Option<GameServer> {
+        logged(self.store.get(server_id))
     }
     pub fn len(&self) -> usize {
-        let servers = self.servers.read().unwrap();
-        servers.len()
+        logged(self.store.len())
     }
     pub fn is_empty(&self) -> bool {
-        let servers = self.servers.read().unwrap();
-        servers.is_empty()
+        self.len() == 0
     }
-    pub fn get(&self, pagination: &Pagination) -> Vec<GameServer> {
-        let servers = self.servers.read().unwrap();
-        servers
-            .values()
-            .skip(pagination.offset.unwrap_or(0))
-            .take(pagination.limit.unwrap_or(usize::MAX))
-            .cloned()
+    pub fn get(&self, query: &ServerQuery) -> Vec<GameServer> {
+        select(logged(self.store.all()), query, self.version_rule)
+            .into_iter()
+            .skip(query.offset.unwrap_or(0))
+            .take(query.limit.unwrap_or(usize::MAX))
             .collect::<Vec<_>>()
     }
+    /// Returns a page of servers starting after the query's cursor, ignoring `offset`
+    pub fn page(&self, query: &ServerQuery) -> Result<ServerPage, String> {
+        let after = match query.cursor.as_deref() {
+            None | Some("") => None,
+            Some(cursor) => {
+                let cursor = Cursor::decode(cursor)?;
+                // the position means nothing in another order or with other servers matching
+                if cursor.query != CursorQuery::from(query) {
+                    return Err(String::from(
+                        "cursor was created with a different sort or filters",
+                    ));
+                }
+                Some(cursor.after)
+            }
+        };
+        let matching = select(logged(self.store.all()), query, self.version_rule);
+        let total = matching.len();
+        let start = match &after {
+            Some(key) => matching.partition_point(|server| {
+                query.compare(&SortKey::from(server), key) != Ordering::Greater
+            }),
+            None => 0,
+        };
+        let limit = query.limit.unwrap_or(usize::MAX);
+        let page = matching
+            .into_iter()
+            .skip(start)
+            .take(limit)
+            .collect::<Vec<_>>();
+        let next_cursor = match page.last() {
+            Some(last) if total - start > page.len() => Some(
+                Cursor {
+                    after: SortKey::from(last),
+                    query: CursorQuery::from(query),
+                }
+                .encode(),
+            ),
+            _ => None,
+        };
+        Ok(ServerPage {
+            servers: page,
+            total,
+            next_cursor,
+        })
+    }
     pub fn update<F: FnOnce(&mut GameServer)>(&self, server_id: &Uuid, func: F) {
-        self.servers
-            .write()
-            .unwrap()
-            .entry(*server_id)
-            .and_modify(func);
+        let _sessions = self.sessions.lock().unwrap();
+        let mut func = Some(func);
+        let updated = self.store.update(server_id, &mut |server| {
+            if let Some(func) = func.take() {
+                func(server);
+            }
+        });
+        if let Some(server) = logged(updated) {
+            self.notify(ServerEvent::Updated { server });
+        }
+    }
+    /// Marks the server as seen just now, without notifying subscribers
+    pub fn touch(&self, server_id: &Uuid) {
+        let _sessions = self.sessions.lock().unwrap();
+        logged(self.store.update(server_id, &mut |server| {
+            server.last_seen = unix_timestamp();
+        }));
+    }
+    /// Returns a snapshot of every server along with a receiver for all changes made after it
+    pub fn subscribe(&self) -> (Vec<GameServer>, broadcast::Receiver<ServerEvent>) {
+        let _sessions = self.sessions.lock().unwrap();
+        let receiver = self.events.subscribe();
+        let mut servers = logged(self.store.all());
+        servers.retain(|server| server.is_visible());
+        (servers, receiver)
+    }
+    fn notify(&self, event: ServerEvent) {
+        send_event(&self.events, event);
     }
 }
 
+fn send_event(events: &broadcast::Sender<ServerEvent>, event: ServerEvent) {
+    // subscribers only know about visible servers, so hiding one removes it for them
+    let event = match event {
+        ServerEvent::Added { server } if !server.is_visible() => return,
+        ServerEvent::Updated { server } if !server.is_visible() => {
+            ServerEvent::Removed { id: server.id }
+        }
+        event => event,
+    };
+    // sending only fails when nobody is subscribed
+    let _ = events.send(event);
+}
+
+// the list keeps working from whatever the store could provide when it fails
+fn logged<T: Default>(result: Result<T, StoreError>) -> T {
+    result.unwrap_or_else(|e| {
+        tracing::error!("server store failed: {}", e);
+        T::default()
+    })
+}
+
 impl Default for ServerList {
     fn default() -> Self {
         Self::new()
     }
 }
 
+fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+// filters and sorts the servers, always breaking ties by id so the order is deterministic
+fn select(servers: Vec<GameServer>, query: &ServerQuery, rule: VersionRule) -> Vec<GameServer> {
+    let mut matching = servers
+        .into_iter()
+        .filter(|server| server.is_visible() && query.matches(server, rule))
+        .collect::<Vec<_>>();
+    matching.sort_by(|a, b| query.compare(&SortKey::from(a), &SortKey::from(b)));
+    matching
+}
+
+/// A page of servers returned in cursor pagination mode
+#[derive(Debug, Serialize)]
+pub struct ServerPage {
+    pub servers: Vec<GameServer>,
+    /// Number of servers matching the query across all pages
+    pub total: usize,
+    /// Pass back as `cursor` to fetch the next page, missing on the last page
+    pub next_cursor: Option<String>,
+}
+
+// The position of a server in the sort order
+#[derive(Debug, Serialize, Deserialize)]
+struct SortKey {
+    id: Uuid,
+    #[serde(default)]
+    featured: bool,
+    players: u32,
+    name: String,
+}
+
+// Where the next page starts and the query it's a page of, encoded into opaque cursors
+#[derive(Debug, Serialize, Deserialize)]
+struct Cursor {
+    after: SortKey,
+    query: CursorQuery,
+}
+
+impl Cursor {
+    fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap())
+    }
+    fn decode(cursor: &str) -> Result<Cursor, String> {
+        URL_SAFE_NO_PAD
+            .decode(cursor)
+            .ok()
+            .and_then(|json| serde_json::from_slice(&json).ok())
+            .ok_or_else(|| format!("invalid cursor: {:?}", cursor))
+    }
+}
+
+// The parts of a query which decide which servers are on the pages and in what order
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct CursorQuery {
+    name: Option<String>,
+    official: Option<bool>,
+    tls: Option<bool>,
+    min_current_players: Option<u32>,
+    max_current_players: Option<u32>,
+    sort: Option<SortBy>,
+    order: Option<SortOrder>,
+    client_version: Option<String>,
+}
+
+impl From<&ServerQuery> for CursorQuery {
+    fn from(query: &ServerQuery) -> Self {
+        CursorQuery {
+            name: query.name.clone(),
+            official: query.official,
+            tls: query.tls,
+            min_current_players: query.min_current_players,
+            max_current_players: query.max_current_players,
+            sort: query.sort,
+            order: query.order,
+            client_version: query.client_version.clone(),
+        }
+    }
+}
+
+impl From<&GameServer> for SortKey {
+    fn from(server: &GameServer) -> Self {
+        SortKey {
+            id: server.id,
+            featured: server.featured,
+            players: server.players,
+            name: server.name.to_lowercase(),
+        }
+    }
+}
+
 // The query parameters for game server index
 #[derive(Debug, Deserialize, Default)]
-pub struct Pagination {
+pub struct ServerQuery {
     pub offset: Option<usize>,
     pub limit: Option<usize>,
+    /// Case-insensitive substring the server name must contain
+    pub name: Option<String>,
+    pub official: Option<bool>,
+    pub tls: Option<bool>,
+    /// Bounds on how many players are on the server right now, not its capacity which is the
+    /// server's `max_players`
+    #[serde(alias = "min_players")]
+    pub min_current_players: Option<u32>,
+    #[serde(alias = "max_players")]
+    pub max_current_players: Option<u32>,
+    pub sort: Option<SortBy>,
+    pub order: Option<SortOrder>,
+    /// Selects cursor pagination, empty for the first page
+    pub cursor: Option<String>,
+    /// Only servers this version of the game can join, see [`VersionRule`]
+    pub client_version: Option<String>,
+}
+
+impl ServerQuery {
+    fn matches(&self, server: &GameServer, rule: VersionRule) -> bool {
+        if let Some(name) = &self.name {
+            if !server.name.to_lowercase().contains(&name.to_lowercase()) {
+                return false;
+            }
+        }
+        if self
+            .official
+            .is_some_and(|official| server.official != official)
+        {
+            return false;
+        }
+        if self.tls.is_some_and(|tls| server.tls != tls) {
+            return false;
+        }
+        if self
+            .min_current_players
+            .is_some_and(|min| server.players < min)
+        {
+            return false;
+        }
+        if self
+            .max_current_players
+            .is_some_and(|max| server.players > max)
+        {
+            return false;
+        }
+        if let Some(client_version) = &self.client_version {
+            let server_version = server.metadata.game_version.as_deref();
+            if !rule.compatible(client_version, server_version) {
+                return false;
+            }
+        }
+        true
+    }
+    fn compare(&self, a: &SortKey, b: &SortKey) -> Ordering {
+        let ordering = match self.sort {
+            Some(sort) => sort.compare(a, b),
+            None => Ordering::Equal,
+        };
+        // featured servers come first whichever way the rest are sorted
+        b.featured.cmp(&a.featured).then_with(|| {
+            self.order
+                .unwrap_or_default()
+                .apply(ordering.then_with(|| a.id.cmp(&b.id)))
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortBy {
+    Players,
+    Name,
+}
+
+impl SortBy {
+    fn compare(&self, a: &SortKey, b: &SortKey) -> Ordering {
+        match self {
+            SortBy::Players => a.players.cmp(&b.players),
+            SortBy::Name => a.name.cmp(&b.name),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+impl SortOrder {
+    fn apply(&self, ordering: Ordering) -> Ordering {
+        match self {
+            SortOrder::Asc => ordering,
+            SortOrder::Desc => ordering.reverse(),
+        }
+    }
+}
+
+/// A game server on 127.0.0.1:12345 with the given number of players, for tests across the crate
+#[cfg(test)]
+pub(crate) fn test_server(name: &str, players: u32, official: bool) -> GameServer {
+    let mut server = GameServer::new(
+        String::from(name),
+        IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+        false,
+        12345,
+        official,
+    );
+    server.players = players;
+    server
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::net::Ipv4Addr;
 
     #[test]
     fn add_server() {
-        let server = GameServer::new(
-            String::from("Test"),
-            IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
-            false,
-            12345,
-            false,
-        );
+        let server = test_server("Test", 0, false);
         let mut server_list = ServerList::new();
         assert_eq!(server_list.len(), 0);
         server_list.add(server);
         assert_eq!(server_list.len(), 1);
     }
 
+    #[test]
+    fn trust_from_token() {
+        // connected from an official range
+        let server = || test_server("Test", 0, true);
+        let unchecked = server().with_trust(Trust::Unchecked);
+        assert!(unchecked.official() && !unchecked.community());
+        // without a token while authentication is enabled
+        let community = server().with_trust(Trust::Community);
+        assert!(!community.official() && community.community());
+        let trusted = server().with_trust(Trust::Trusted);
+        assert!(!trusted.official() && !trusted.community());
+        let official = server().with_trust(Trust::Official);
+        assert!(official.official() && !official.community());
+    }
+
     #[test]
     fn remove_server() {
-        let server = GameServer::new(
-            String::from("Test"),
-            IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
-            false,
-            12345,
-            false,
-        );
+        let server = test_server("Test", 0, false);
         let mut server_list = ServerList::new();
         let uuid = server_list.add(server);
         assert_eq!(server_list.len(), 1);
@@ -149,35 +1116,473 @@ mod tests {
 
     #[test]
     fn get_server() {
-        let server = GameServer::new(
-            String::from("Test"),
-            IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
-            false,
-            12345,
-            false,
-        );
-        let expected = server.clone();
+        let server = test_server("Test", 0, false);
+        let mut expected = server.clone();
         let mut server_list = ServerList::new();
-        server_list.add(server);
-        let pagination = Pagination::default();
-        let servers = server_list.get(&pagination);
+        expected.id = server_list.add(server);
+        expected.last_seen = server_list.get(&ServerQuery::default())[0].last_seen;
+        let query = ServerQuery::default();
+        let servers = server_list.get(&query);
         assert_eq!(servers.len(), 1);
         assert_eq!(servers[0], expected);
     }
     #[test]
     fn update_server() {
-        let server = GameServer::new(
-            String::from("Test"),
-            IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
-            false,
-            12345,
-            false,
-        );
+        let server = test_server("Test", 0, false);
         let mut server_list = ServerList::new();
         let server_id = server_list.add(server);
         server_list.update(&server_id, |game_server| game_server.players = 10);
-        let pagination = Pagination::default();
-        let updated_server = server_list.get(&pagination);
+        let query = ServerQuery::default();
+        let updated_server = server_list.get(&query);
         assert_eq!(updated_server[0].players, 10)
     }
+
+    #[test]
+    fn filter_servers() {
+        let mut server_list = ServerList::new();
+        server_list.add(test_server("Alpha Race", 2, true));
+        server_list.add(test_server("Beta Race", 8, false));
+        server_list.add(test_server("Gamma", 5, false));
+
+        let query = ServerQuery {
+            name: Some(String::from("race")),
+            ..Default::default()
+        };
+        assert_eq!(server_list.get(&query).len(), 2);
+
+        let query = ServerQuery {
+            official: Some(false),
+            min_current_players: Some(3),
+            max_current_players: Some(6),
+            ..Default::default()
+        };
+        let servers = server_list.get(&query);
+        assert_eq!(servers.len(), 1);
+        assert_eq!(servers[0].name, "Gamma");
+    }
+
+    #[test]
+    fn filter_by_client_version() {
+        let mut server_list = ServerList::new().with_version_rule(VersionRule::Semver);
+        for (name, version) in [
+            ("Old", Some("1.4.2")),
+            ("New", Some("2.1")),
+            ("Unknown", None),
+        ] {
+            server_list.add(test_server(name, 0, false).with_metadata(ServerMetadata {
+                game_version: version.map(String::from),
+                ..Default::default()
+            }));
+        }
+        let query = ServerQuery {
+            client_version: Some(String::from("2.0.0")),
+            ..Default::default()
+        };
+        let servers = server_list.get(&query);
+        assert_eq!(servers.len(), 1);
+        assert_eq!(servers[0].name, "New");
+        assert_eq!(server_list.get(&ServerQuery::default()).len(), 3);
+    }
+
+    #[test]
+    fn featured_servers_first() {
+        let mut server_list = ServerList::new();
+        server_list.add(test_server("a", 2, false));
+        let featured = server_list.add(test_server("b", 8, false));
+        server_list.add(test_server("c", 5, false));
+        assert!(server_list.set_featured(&featured, true));
+        assert!(!server_list.set_featured(&Uuid::nil(), true));
+
+        let query = ServerQuery {
+            sort: Some(SortBy::Players),
+            ..Default::default()
+        };
+        let names = |servers: Vec<GameServer>| {
+            servers
+                .into_iter()
+                .map(|server| server.name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(server_list.get(&query)), vec!["b", "a", "c"]);
+
+        let mut query = ServerQuery {
+            sort: Some(SortBy::Players),
+            cursor: Some(String::new()),
+            limit: Some(1),
+            ..Default::default()
+        };
+        let mut paged = Vec::new();
+        while query.cursor.is_some() {
+            let page = server_list.page(&query).unwrap();
+            paged.extend(page.servers);
+            query.cursor = page.next_cursor;
+        }
+        assert_eq!(names(paged), vec!["b", "a", "c"]);
+    }
+
+    #[test]
+    fn sort_servers() {
+        let mut server_list = ServerList::new();
+        server_list.add(test_server("b", 2, false));
+        server_list.add(test_server("C", 8, false));
+        server_list.add(test_server("a", 5, false));
+
+        let query = ServerQuery {
+            sort: Some(SortBy::Players),
+            order: Some(SortOrder::Desc),
+            ..Default::default()
+        };
+        let players = server_list
+            .get(&query)
+            .iter()
+            .map(|server| server.players)
+            .collect::<Vec<_>>();
+        assert_eq!(players, vec![8, 5, 2]);
+
+        let query = ServerQuery {
+            sort: Some(SortBy::Name),
+            limit: Some(2),
+            ..Default::default()
+        };
+        let names = server_list
+            .get(&query)
+            .into_iter()
+            .map(|server| server.name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["a", "b"]);
+    }
+
+    #[test]
+    fn registration_order() {
+        let mut server_list = ServerList::new();
+        let ids = (0..20)
+            .map(|i| server_list.add(test_server(&i.to_string(), 0, false)))
+            .collect::<Vec<_>>();
+        let listed = server_list
+            .get(&ServerQuery::default())
+            .iter()
+            .map(|server| server.id())
+            .collect::<Vec<_>>();
+        assert_eq!(listed, ids);
+    }
+
+    #[test]
+    fn cursor_pagination() {
+        let mut server_list = ServerList::new();
+        for players in [3, 1, 3, 2, 5] {
+            server_list.add(test_server("Test", players, false));
+        }
+        let mut query = ServerQuery {
+            sort: Some(SortBy::Players),
+            limit: Some(2),
+            cursor: Some(String::new()),
+            ..Default::default()
+        };
+
+        let first = server_list.page(&query).unwrap();
+        assert_eq!(first.total, 5);
+        assert_eq!(first.servers.len(), 2);
+        assert_eq!(first.servers[1].players, 2);
+
+        // removing an already seen server must not shift the next page
+        server_list.remove(&first.servers[0].id());
+        query.cursor = first.next_cursor;
+        let second = server_list.page(&query).unwrap();
+        assert_eq!(second.total, 4);
+        let players = second.servers.iter().map(|s| s.players).collect::<Vec<_>>();
+        assert_eq!(players, vec![3, 3]);
+
+        query.cursor = second.next_cursor;
+        let third = server_list.page(&query).unwrap();
+        assert_eq!(third.servers.len(), 1);
+        assert_eq!(third.servers[0].players, 5);
+        assert_eq!(third.next_cursor, None);
+    }
+
+    #[test]
+    fn invalid_cursor() {
+        let server_list = ServerList::new();
+        let query = ServerQuery {
+            cursor: Some(String::from("not a cursor")),
+            ..Default::default()
+        };
+        assert!(server_list.page(&query).is_err());
+    }
+
+    #[test]
+    fn reject_cursor_from_another_query() {
+        let mut server_list = ServerList::new();
+        for players in [1, 2, 3] {
+            server_list.add(test_server("Test", players, false));
+        }
+        let mut query = ServerQuery {
+            sort: Some(SortBy::Players),
+            limit: Some(1),
+            cursor: Some(String::new()),
+            ..Default::default()
+        };
+        query.cursor = server_list.page(&query).unwrap().next_cursor;
+        assert!(server_list.page(&query).is_ok());
+        query.order = Some(SortOrder::Desc);
+        assert!(server_list.page(&query).is_err());
+        query.order = None;
+        query.min_current_players = Some(2);
+        assert!(server_list.page(&query).is_err());
+    }
+
+    #[test]
+    fn query_player_bound_aliases() {
+        let query: ServerQuery =
+            serde_json::from_str(r#"{"min_players": 2, "max_players": 8}"#).unwrap();
+        assert_eq!(query.min_current_players, Some(2));
+        assert_eq!(query.max_current_players, Some(8));
+    }
+
+    #[test]
+    fn touch_server() {
+        let mut server_list = ServerList::new();
+        let server_id = server_list.add(test_server("Test", 0, false));
+        server_list.update(&server_id, |game_server| game_server.last_seen = 0);
+        server_list.touch(&server_id);
+        assert!(server_list.get(&ServerQuery::default())[0].last_seen > 0);
+    }
+
+    #[test]
+    fn serialize_list_messages() {
+        let registered = ListMessage::Registered {
+            id: Uuid::nil(),
+            public_ip: IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+            official: true,
+            community: false,
+            resume_token: String::from("abc"),
+            resumed: false,
+            name: String::from("Test"),
+            flagged: false,
+        };
+        assert_eq!(
+            serde_json::to_string(&registered).unwrap(),
+            "{\"type\":\"registered\",\"id\":\"00000000-0000-0000-0000-000000000000\",\"public_ip\":\"127.0.0.1\",\"official\":true,\"community\":false,\"resume_token\":\"abc\",\"resumed\":false,\"name\":\"Test\",\"flagged\":false}"
+        );
+        let error = ListMessage::from(ListError::new(ErrorCode::InvalidGameMessage, "bad"));
+        assert_eq!(
+            serde_json::to_string(&error).unwrap(),
+            "{\"type\":\"error\",\"code\":\"invalid_game_message\",\"message\":\"bad\"}"
+        );
+    }
+
+    #[test]
+    fn subscribe_to_changes() {
+        let mut server_list = ServerList::new();
+        let existing_id = server_list.add(test_server("Existing", 0, false));
+        let (snapshot, mut events) = server_list.subscribe();
+        assert_eq!(snapshot.len(), 1);
+        assert_eq!(snapshot[0].id(), existing_id);
+
+        let server_id = server_list.add(test_server("New", 0, false));
+        server_list.update(&server_id, |game_server| game_server.players = 3);
+        server_list.touch(&server_id);
+        server_list.remove(&existing_id);
+
+        match events.try_recv().unwrap() {
+            ServerEvent::Added { server } => assert_eq!(server.id(), server_id),
+            event => panic!("unexpected event: {:?}", event),
+        }
+        match events.try_recv().unwrap() {
+            ServerEvent::Updated { server } => assert_eq!(server.players, 3),
+            event => panic!("unexpected event: {:?}", event),
+        }
+        assert_eq!(
+            events.try_recv().unwrap(),
+            ServerEvent::Removed { id: existing_id }
+        );
+        assert!(events.try_recv().is_err());
+    }
+
+    #[test]
+    fn advertise_both_address_families() {
+        let server = test_server("Test", 0, false);
+        assert_eq!(server.ipv4(), Some(Ipv4Addr::new(127, 0, 0, 1)));
+        assert_eq!(server.ipv6(), None);
+        let server = server
+            .with_alternate_ip(IpAddr::V6(Ipv6Addr::LOCALHOST))
+            .with_alternate_ip(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)));
+        assert_eq!(server.ip(), IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)));
+        assert_eq!(server.ipv4(), Some(Ipv4Addr::new(127, 0, 0, 1)));
+        assert_eq!(server.ipv6(), Some(Ipv6Addr::LOCALHOST));
+    }
+
+    #[test]
+    fn hide_unreachable_servers() {
+        let mut server_list = ServerList::new();
+        let mut hidden = test_server("Hidden", 0, false);
+        hidden.reachable = Some(false);
+        let hidden_id = server_list.add(hidden);
+        server_list.add(test_server("Visible", 0, false));
+        let (snapshot, mut events) = server_list.subscribe();
+        assert_eq!(snapshot.len(), 1);
+        assert_eq!(server_list.get(&ServerQuery::default()).len(), 1);
+
+        server_list.update(&hidden_id, |game_server| game_server.reachable = Some(true));
+        assert_eq!(server_list.get(&ServerQuery::default()).len(), 2);
+        server_list.update(&hidden_id, |game_server| {
+            game_server.reachable = Some(false)
+        });
+        match events.try_recv().unwrap() {
+            ServerEvent::Updated { server } => assert_eq!(server.id(), hidden_id),
+            event => panic!("unexpected event: {:?}", event),
+        }
+        assert_eq!(
+            events.try_recv().unwrap(),
+            ServerEvent::Removed { id: hidden_id }
+        );
+    }
+
+    #[test]
+    fn resume_server() {
+        let mut server_list = ServerList::new();
+        let first = server_list.connect(test_server("Test", 4, false), None);
+        assert!(!first.resumed);
+        server_list.update(&first.id, |server| server.reserved = 2);
+        assert!(server_list.disconnect(&first));
+        let servers = server_list.get(&ServerQuery::default());
+        assert_eq!(servers[0].status(), ServerStatus::Disconnected);
+
+        server_list.update(&first.id, |server| server.reachable = Some(true));
+        let mut renamed = test_server("Renamed", 0, false);
+        renamed.reachable = Some(false);
+        let second = server_list.connect(renamed, Some(&first.resume_token));
+        assert!(second.resumed);
+        assert_eq!(second.id, first.id);
+        assert_eq!(second.resume_token, first.resume_token);
+        let servers = server_list.get(&ServerQuery::default());
+        assert_eq!(servers.len(), 1);
+        assert_eq!(servers[0].name, "Renamed");
+        assert_eq!(servers[0].players, 4);
+        assert_eq!(servers[0].reserved, 2);
+        assert_eq!(servers[0].status(), ServerStatus::Online);
+        assert!(servers[0].is_visible());
+
+        // the replaced connection can't mark the resumed server as disconnected
+        assert!(!server_list.disconnect(&first));
+        assert_eq!(server_list.remove_expired(Duration::ZERO), Vec::new());
+
+        let unknown = server_list.connect(test_server("Other", 0, false), Some("unknown"));
+        assert!(!unknown.resumed);
+        assert_ne!(unknown.id, first.id);
+    }
+
+    #[test]
+    fn expire_disconnected_servers() {
+        let mut server_list = ServerList::new();
+        let registration = server_list.connect(test_server("Test", 0, false), None);
+        server_list.disconnect(&registration);
+        assert!(server_list
+            .remove_expired(Duration::from_secs(60))
+            .is_empty());
+        assert_eq!(server_list.remove_expired(Duration::ZERO).len(), 1);
+        assert!(server_list.is_empty());
+        let resumed = server_list.connect(
+            test_server("Test", 0, false),
+            Some(&registration.resume_token),
+        );
+        assert!(!resumed.resumed);
+    }
+
+    #[test]
+    fn hold_ip_slot_until_removed() {
+        let ip = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7));
+        let counter = limit::IpCounter::new();
+        let mut server_list = ServerList::new();
+        let registration = server_list.connect(test_server("Test", 0, false), None);
+        let ip_slot = Arc::new(counter.try_acquire(ip, 1).unwrap());
+        server_list.hold_ip_slot(&registration, ip_slot);
+        server_list.disconnect(&registration);
+        assert_eq!(counter.count(&ip), 1);
+        // resuming hands the same slot on rather than taking another
+        let ip_slot = server_list.ip_slot(&registration.resume_token).unwrap();
+        assert!(counter.try_acquire(ip, 1).is_none());
+        let resumed = server_list.connect(
+            test_server("Test", 0, false),
+            Some(&registration.resume_token),
+        );
+        server_list.hold_ip_slot(&resumed, ip_slot);
+        server_list.disconnect(&resumed);
+        server_list.remove_expired(Duration::ZERO);
+        assert_eq!(counter.count(&ip), 0);
+    }
+
+    #[test]
+    fn restore_snapshot() {
+        let mut server_list = ServerList::new();
+        let registration = server_list.connect(test_server("Test", 3, false), None);
+        server_list.add(test_server("No Session", 0, false));
+        let snapshot = server_list.snapshot();
+        assert_eq!(snapshot.servers.len(), 1);
+
+        let mut restarted = ServerList::new();
+        let restored = restarted.restore(snapshot);
+        assert_eq!(restored.len(), 1);
+        assert_eq!(restored[0].id, registration.id);
+        assert_eq!(restored[0].status(), ServerStatus::Pending);
+        assert_eq!(restarted.len(), 1);
+
+        let resumed = restarted.connect(
+            test_server("Test", 0, false),
+            Some(&registration.resume_token),
+        );
+        assert!(resumed.resumed);
+        assert_eq!(resumed.id, registration.id);
+        let servers = restarted.get(&ServerQuery::default());
+        assert_eq!(servers[0].players, 3);
+        assert_eq!(servers[0].status(), ServerStatus::Online);
+    }
+
+    #[test]
+    fn expire_pending_servers() {
+        let mut server_list = ServerList::new();
+        server_list.connect(test_server("Test", 0, false), None);
+        let mut restarted = ServerList::new();
+        restarted.restore(server_list.snapshot());
+        assert_eq!(restarted.remove_expired(Duration::ZERO).len(), 1);
+        assert!(restarted.is_empty());
+    }
+
+    #[test]
+    fn sync_peer_servers() {
+        let mut server_list = ServerList::new();
+        let local_id = server_list.add(test_server("Local", 0, false));
+        let (_, mut events) = server_list.subscribe();
+
+        let mut peer = ServerList::new();
+        let first = peer.add(test_server("First", 1, false));
+        peer.add(test_server("Second", 2, false));
+        server_list.sync_peer("eu-2", peer.local());
+        assert_eq!(server_list.len(), 3);
+        assert_eq!(server_list.local().len(), 1);
+        assert!(matches!(events.try_recv(), Ok(ServerEvent::Added { .. })));
+        assert!(matches!(events.try_recv(), Ok(ServerEvent::Added { .. })));
+
+        let second = peer.local()[1].id;
+        peer.update(&second, |server| server.last_seen = 0);
+        server_list.sync_peer("eu-2", peer.local());
+        assert!(events.try_recv().is_err());
+        assert_eq!(server_list.server(&second).unwrap().last_seen, 0);
+
+        peer.remove(&first);
+        peer.update(&second, |server| server.players = 5);
+        server_list.sync_peer("eu-2", peer.local());
+        let servers = server_list.get(&ServerQuery::default());
+        assert_eq!(servers.len(), 2);
+        assert_eq!(servers[1].players, 5);
+        assert_eq!(servers[1].origin(), Some("eu-2"));
+
+        // a peer can't replace servers connected to this instance
+        let mut clash = test_server("Clash", 0, false);
+        clash.id = local_id;
+        server_list.sync_peer("eu-3", vec![clash]);
+        assert_eq!(server_list.local()[0].name, "Local");
+
+        assert_eq!(server_list.remove_peer("eu-2"), 1);
+        assert_eq!(server_list.len(), 1);
+    }
 }
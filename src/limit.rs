@@ -0,0 +1,282 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    net::{IpAddr, Ipv6Addr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Allows bursts of up to `burst` actions, refilling at `rate` actions per second
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(rate: f64, burst: u32) -> TokenBucket {
+        TokenBucket {
+            rate,
+            burst: f64::from(burst.max(1)),
+            tokens: f64::from(burst.max(1)),
+            last_refill: Instant::now(),
+        }
+    }
+    /// Takes a token if one is available
+    pub fn try_take(&mut self) -> bool {
+        self.try_take_at(Instant::now())
+    }
+    fn try_take_at(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * self.rate).min(self.burst);
+        self.last_refill = now;
+    }
+    fn is_full(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        self.tokens >= self.burst
+    }
+}
+
+/// The address limits are kept for, IPv6 clients usually have a whole /64 to pick addresses from
+/// so it counts as one
+fn limit_key(ip: IpAddr) -> IpAddr {
+    match ip.to_canonical() {
+        IpAddr::V6(ipv6) => IpAddr::V6(Ipv6Addr::from(u128::from(ipv6) & !u128::from(u64::MAX))),
+        ipv4 => ipv4,
+    }
+}
+
+// the most IPs tracked at once, the oldest buckets are forgotten to make room for new ones
+const MAX_BUCKETS: usize = 100_000;
+// how often buckets which have refilled are forgotten
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug)]
+struct Buckets {
+    by_ip: HashMap<IpAddr, TokenBucket>,
+    // IPs in the order their buckets were created, oldest first
+    created: VecDeque<IpAddr>,
+    last_sweep: Instant,
+}
+
+/// A token bucket for every client IP
+#[derive(Debug, Clone)]
+pub struct IpRateLimiter {
+    rate: f64,
+    burst: u32,
+    max_buckets: usize,
+    buckets: Arc<Mutex<Buckets>>,
+}
+
+impl IpRateLimiter {
+    pub fn new(rate: f64, burst: u32) -> IpRateLimiter {
+        IpRateLimiter {
+            rate,
+            burst,
+            max_buckets: MAX_BUCKETS,
+            buckets: Arc::new(Mutex::new(Buckets {
+                by_ip: HashMap::new(),
+                created: VecDeque::new(),
+                last_sweep: Instant::now(),
+            })),
+        }
+    }
+    /// Returns whether the IP is allowed another request
+    pub fn check(&self, ip: IpAddr) -> bool {
+        self.check_at(ip, Instant::now())
+    }
+    fn check_at(&self, ip: IpAddr, now: Instant) -> bool {
+        let ip = limit_key(ip);
+        let mut buckets = self.buckets.lock().unwrap();
+        let Buckets {
+            by_ip,
+            created,
+            last_sweep,
+        } = &mut *buckets;
+        if now.saturating_duration_since(*last_sweep) >= SWEEP_INTERVAL {
+            // a full bucket behaves the same as a new one so it can be forgotten
+            by_ip.retain(|_, bucket| !bucket.is_full(now));
+            created.retain(|ip| by_ip.contains_key(ip));
+            *last_sweep = now;
+        }
+        if !by_ip.contains_key(&ip) {
+            while by_ip.len() >= self.max_buckets {
+                let Some(oldest) = created.pop_front() else {
+                    break;
+                };
+                by_ip.remove(&oldest);
+            }
+            created.push_back(ip);
+        }
+        by_ip
+            .entry(ip)
+            .or_insert_with(|| TokenBucket {
+                last_refill: now,
+                ..TokenBucket::new(self.rate, self.burst)
+            })
+            .try_take_at(now)
+    }
+}
+
+/// Counts the game servers registered from each IP
+#[derive(Debug, Clone, Default)]
+pub struct IpCounter {
+    counts: Arc<Mutex<HashMap<IpAddr, usize>>>,
+}
+
+impl IpCounter {
+    pub fn new() -> IpCounter {
+        IpCounter::default()
+    }
+    /// Counts another server for the IP unless it already has `max`, the returned slot must be
+    /// kept until the server is removed
+    pub fn try_acquire(&self, ip: IpAddr, max: usize) -> Option<IpSlot> {
+        let ip = limit_key(ip);
+        let mut counts = self.counts.lock().unwrap();
+        let count = counts.entry(ip).or_default();
+        if *count >= max {
+            if *count == 0 {
+                counts.remove(&ip);
+            }
+            return None;
+        }
+        *count += 1;
+        Some(IpSlot {
+            ip,
+            counts: self.counts.clone(),
+        })
+    }
+    pub fn count(&self, ip: &IpAddr) -> usize {
+        let counts = self.counts.lock().unwrap();
+        counts.get(&limit_key(*ip)).copied().unwrap_or(0)
+    }
+}
+
+/// Stops counting the server once dropped
+#[derive(Debug)]
+pub struct IpSlot {
+    ip: IpAddr,
+    counts: Arc<Mutex<HashMap<IpAddr, usize>>>,
+}
+
+impl IpSlot {
+    /// Whether the slot counts towards the IP's limit, which is shared by its IPv6 network
+    pub fn is_for(&self, ip: IpAddr) -> bool {
+        self.ip == limit_key(ip)
+    }
+}
+
+impl Drop for IpSlot {
+    fn drop(&mut self) {
+        let mut counts = self.counts.lock().unwrap();
+        if let Some(count) = counts.get_mut(&self.ip) {
+            *count -= 1;
+            if *count == 0 {
+                counts.remove(&self.ip);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{net::Ipv4Addr, time::Duration};
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    #[test]
+    fn token_bucket_refills() {
+        let mut bucket = TokenBucket::new(2.0, 2);
+        let start = bucket.last_refill;
+        assert!(bucket.try_take_at(start));
+        assert!(bucket.try_take_at(start));
+        assert!(!bucket.try_take_at(start));
+        assert!(bucket.try_take_at(start + Duration::from_millis(500)));
+        assert!(!bucket.try_take_at(start + Duration::from_millis(500)));
+    }
+
+    #[test]
+    fn rate_limit_per_ip() {
+        let limiter = IpRateLimiter::new(0.0, 1);
+        let first = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let second = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+        assert!(limiter.check(first));
+        assert!(!limiter.check(first));
+        assert!(limiter.check(second));
+    }
+
+    #[test]
+    fn rate_limit_per_ipv6_network() {
+        let limiter = IpRateLimiter::new(0.0, 1);
+        assert!(limiter.check(ip("2001:db8::1")));
+        assert!(!limiter.check(ip("2001:db8::ffff:2")));
+        assert!(limiter.check(ip("2001:db8:0:1::1")));
+        assert!(limiter.check(ip("10.0.0.1")));
+        assert!(!limiter.check(ip("::ffff:10.0.0.1")));
+    }
+
+    #[test]
+    fn forget_old_buckets() {
+        let limiter = IpRateLimiter {
+            max_buckets: 2,
+            ..IpRateLimiter::new(1.0, 1)
+        };
+        let ip = |last| IpAddr::V4(Ipv4Addr::new(10, 0, 0, last));
+        let start = Instant::now();
+        assert!(limiter.check_at(ip(1), start));
+        assert!(limiter.check_at(ip(2), start));
+        assert!(!limiter.check_at(ip(1), start));
+        // the oldest bucket makes room for a new IP
+        assert!(limiter.check_at(ip(3), start));
+        assert!(limiter.check_at(ip(1), start));
+        assert!(!limiter.check_at(ip(3), start));
+        assert_eq!(limiter.buckets.lock().unwrap().by_ip.len(), 2);
+
+        // refilled buckets are swept once the interval has passed
+        assert!(limiter.check_at(ip(1), start + SWEEP_INTERVAL));
+        let buckets = limiter.buckets.lock().unwrap();
+        assert_eq!(buckets.by_ip.len(), 1);
+        assert_eq!(buckets.created, [ip(1)]);
+    }
+
+    #[test]
+    fn count_servers_per_ip() {
+        let counter = IpCounter::new();
+        let ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let first = counter.try_acquire(ip, 2);
+        let second = counter.try_acquire(ip, 2);
+        assert!(first.is_some() && second.is_some());
+        assert!(counter.try_acquire(ip, 2).is_none());
+        drop(first);
+        assert_eq!(counter.count(&ip), 1);
+        assert!(counter.try_acquire(ip, 2).is_some());
+    }
+
+    #[test]
+    fn count_servers_per_ipv6_network() {
+        let counter = IpCounter::new();
+        let first = counter.try_acquire(ip("2001:db8::1"), 1);
+        assert!(first.is_some());
+        assert!(counter.try_acquire(ip("2001:db8::2"), 1).is_none());
+        assert!(counter.try_acquire(ip("2001:db8:0:1::1"), 1).is_some());
+        assert_eq!(counter.count(&ip("2001:db8::3")), 1);
+        let mapped = counter.try_acquire(ip("::ffff:10.0.0.1"), 1);
+        assert!(mapped.is_some());
+        assert!(counter.try_acquire(ip("10.0.0.1"), 1).is_none());
+        drop(first);
+        assert_eq!(counter.count(&ip("2001:db8::1")), 0);
+    }
+}
@@ -3,8 +3,9 @@
 //! Uses websockets to connect game servers and update their state.
 //!
 //! API is:
-//! - `GET /api/list/servers`: return a JSON list of servers.
+//! - `GET /api/list/servers`: return a JSON list of servers, optionally filtered and sorted.
 //! - `WS /api/list/ws`: connect a game server and update it's state.
+//! - `WS /api/list/subscribe`: receive a snapshot of the list followed by live changes.
 //!
 //! See README for more details.
 //!
@@ -17,22 +18,52 @@
 use axum::{
     error_handling::HandleErrorLayer,
     extract::{
-        ws::{Message, WebSocket, WebSocketUpgrade},
-        Query, State,
+        ws::{close_code, CloseFrame, Message, WebSocket, WebSocketUpgrade},
+        Path, Query, State,
     },
-    http::StatusCode,
-    response::IntoResponse,
-    routing::get,
-    Json, Router,
+    headers::{authorization::Bearer, Authorization},
+    http::{Request, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{delete, get, post, put},
+    Json, Router, TypedHeader,
 };
 use axum_client_ip::{SecureClientIp, SecureClientIpSource};
-use gameserverlist::{ConnectMessage, GameMessage, GameServer, Pagination, ServerList};
+use gameserverlist::auth::constant_time_eq;
+use gameserverlist::bans::{self, BanList, BanTarget};
+use gameserverlist::federation::{follow_peer, Federation, Peer, PeerList};
+use gameserverlist::games::{parse_games, Game, GameConfig, DEFAULT_BURST, DEFAULT_GAME};
+use gameserverlist::hostname::{
+    deserialize_allowlist, AllowedHostname, HostnameVerifier, SystemResolver,
+};
+use gameserverlist::limit::{IpSlot, TokenBucket};
+use gameserverlist::names::{CheckedName, ModerationAction, NamePolicy, WordList};
+use gameserverlist::network::{
+    check_alternate_ip, deserialize_ranges, private_ranges, AddressRanges,
+};
+use gameserverlist::probe::{probe_loop, ProbeConfig};
+use gameserverlist::quickjoin::{ConnectionDetails, QuickJoinQuery, ScoringPolicy};
+use gameserverlist::reservation::{Answer, Reservations, TicketClaims, TicketSigner};
+use gameserverlist::snapshot;
+use gameserverlist::store::{MemoryStore, RedisStore, ServerStore};
+use gameserverlist::version::{deserialize_version, VersionRule};
+use gameserverlist::{
+    ConnectMessage, ConnectOptions, ErrorCode, GameMessage, GameServer, ListError, ListMessage,
+    ServerEvent, ServerList, ServerMetadata, ServerQuery, ServerStatus,
+};
+use ipnet::IpNet;
 use lazy_static::lazy_static;
-use prometheus::{IntCounter, IntGauge, Registry};
+use prometheus::{IntCounterVec, IntGaugeVec, Opts, Registry};
+use semver::Version;
+use socket2::{Domain, Protocol, Socket, Type};
 use std::{
-    net::{IpAddr, SocketAddr},
-    time::Duration,
+    collections::HashMap,
+    net::{IpAddr, Ipv6Addr, SocketAddr, TcpListener},
+    path::{Path as FilePath, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
 };
+use tokio::sync::{broadcast::error::RecvError, mpsc, oneshot, watch};
 use tower::{BoxError, ServiceBuilder};
 use tower_http::trace::TraceLayer;
 use tracing::instrument;
@@ -42,14 +73,27 @@ use uuid::Uuid;
 // define prometheus metrics
 lazy_static! {
     pub static ref REGISTRY: Registry = Registry::new();
-    pub static ref SERVER_LIST_REQUESTS: IntCounter =
-        IntCounter::new("server_list_requests", "Server List Requests")
+    pub static ref SERVER_LIST_REQUESTS: IntCounterVec = IntCounterVec::new(
+        Opts::new("server_list_requests", "Server List Requests"),
+        &["game"]
+    )
+    .expect("metric can be created");
+    pub static ref CONNECTED_GAME_SERVERS: IntGaugeVec = IntGaugeVec::new(
+        Opts::new("connected_game_servers", "Connected Game Servers"),
+        &["game"]
+    )
+    .expect("metric can be created");
+    pub static ref IN_GAME_PLAYERS: IntGaugeVec =
+        IntGaugeVec::new(Opts::new("in_game_players", "In Game Players"), &["game"])
             .expect("metric can be created");
-    pub static ref CONNECTED_GAME_SERVERS: IntGauge =
-        IntGauge::new("connected_game_servers", "Connected Game Servers")
+    pub static ref LIST_SUBSCRIBERS: IntGaugeVec =
+        IntGaugeVec::new(Opts::new("list_subscribers", "List Subscribers"), &["game"])
             .expect("metric can be created");
-    pub static ref IN_GAME_PLAYERS: IntGauge =
-        IntGauge::new("in_game_players", "In Game Players").expect("metric can be created");
+    pub static ref RATE_LIMITED: IntCounterVec = IntCounterVec::new(
+        Opts::new("rate_limited", "Rejected By Rate Limits"),
+        &["game", "limit"]
+    )
+    .expect("metric can be created");
 }
 
 fn register_custom_metrics() {
@@ -64,24 +108,361 @@ fn register_custom_metrics() {
     REGISTRY
         .register(Box::new(IN_GAME_PLAYERS.clone()))
         .expect("collector can be registered");
+
+    REGISTRY
+        .register(Box::new(LIST_SUBSCRIBERS.clone()))
+        .expect("collector can be registered");
+
+    REGISTRY
+        .register(Box::new(RATE_LIMITED.clone()))
+        .expect("collector can be registered");
 }
 
 // env config with defaults
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, Debug, Clone)]
 struct Config {
+    #[serde(default = "default_bind_addr")]
+    bind_addr: IpAddr,
+    #[serde(default = "default_port")]
+    port: u16,
+    // skips detecting the public ip when set
+    public_ip: Option<IpAddr>,
+    // also advertised for game servers listed under the public ip
+    public_ipv6: Option<Ipv6Addr>,
+    // panic if the public ip can't be detected, otherwise local game servers keep their own ip
+    #[serde(default = "default_public_ip_required")]
+    public_ip_required: bool,
     #[serde(default = "default_ip_source")]
     ip_source: SecureClientIpSource,
+    // hostnames game servers can advertise without them resolving to the server
+    #[serde(default, deserialize_with = "deserialize_allowlist")]
+    hostname_allowlist: Vec<AllowedHostname>,
+    // game servers connecting from these ranges are official
+    #[serde(default = "private_ranges", deserialize_with = "deserialize_ranges")]
+    official_ranges: Vec<IpNet>,
+    // game servers connecting from these ranges are listed under the public ip
+    #[serde(default = "private_ranges", deserialize_with = "deserialize_ranges")]
+    rewrite_ranges: Vec<IpNet>,
+    #[serde(
+        default = "default_heartbeat_interval_secs",
+        deserialize_with = "deserialize_interval"
+    )]
+    heartbeat_interval_secs: u64,
+    // a zero timeout would evict every game server on its first heartbeat
+    #[serde(
+        default = "default_idle_timeout_secs",
+        deserialize_with = "deserialize_interval"
+    )]
+    idle_timeout_secs: u64,
+    // tokens game servers can register with, official tokens also mark the server as official
+    #[serde(default)]
+    auth_tokens: Vec<String>,
+    #[serde(default)]
+    official_tokens: Vec<String>,
+    // reject game servers without a token instead of listing them as community servers
+    #[serde(default)]
+    auth_required: bool,
+    // limits are disabled unless configured
+    max_servers_per_ip: Option<usize>,
+    // game messages per second allowed for each game server
+    game_message_rate: Option<f64>,
+    #[serde(default = "default_burst")]
+    game_message_burst: u32,
+    // server list requests per second allowed for each IP
+    list_request_rate: Option<f64>,
+    #[serde(default = "default_burst")]
+    list_request_burst: u32,
+    // only list game servers once a TCP connection can be made to them
+    #[serde(default)]
+    probe_enabled: bool,
+    #[serde(default = "default_probe_timeout_ms")]
+    probe_timeout_ms: u64,
+    #[serde(
+        default = "default_probe_interval_secs",
+        deserialize_with = "deserialize_interval"
+    )]
+    probe_interval_secs: u64,
+    // how long a disconnected game server stays listed for it to resume its entry
+    #[serde(default = "default_resume_grace_secs")]
+    resume_grace_secs: u64,
+    // game server names are limited to this many characters
+    #[serde(default = "default_name_min_length")]
+    name_min_length: usize,
+    #[serde(default = "default_name_max_length")]
+    name_max_length: usize,
+    // characters game server names can't contain, on top of control characters
+    #[serde(default)]
+    name_disallowed_chars: String,
+    // file of words not allowed in game server names, one per line
+    name_blocklist_path: Option<PathBuf>,
+    // what happens to names with blocked words
+    #[serde(default)]
+    name_filter_action: ModerationAction,
+    // which game servers are listed for a client's version
+    #[serde(default)]
+    version_rule: VersionRule,
+    // how much quick join prefers nearly full servers and official servers
+    #[serde(default = "default_quickjoin_fill_weight")]
+    quickjoin_fill_weight: f64,
+    #[serde(default = "default_quickjoin_official_weight")]
+    quickjoin_official_weight: f64,
+    // game servers older than this are rejected
+    #[serde(default, deserialize_with = "deserialize_version")]
+    min_game_version: Option<Version>,
+    // json file with the config of each game, otherwise only the default game is served
+    games_config: Option<PathBuf>,
+    // where game servers are stored, redis lets several instances share them
+    #[serde(default)]
+    store: StoreBackend,
+    redis_url: Option<String>,
+    #[serde(default = "default_redis_key")]
+    redis_key: String,
+    // name peers list this instance's game servers under
+    #[serde(default = "default_instance_name")]
+    instance_name: String,
+    // token peers must present to fetch game servers, federation is disabled when unset
+    federation_token: Option<String>,
+    // base urls of other instances to list game servers from
+    #[serde(default)]
+    federation_peers: Vec<String>,
+    #[serde(
+        default = "default_federation_interval_secs",
+        deserialize_with = "deserialize_interval"
+    )]
+    federation_interval_secs: u64,
+    // a peer's game servers are removed after it's been silent for this long
+    #[serde(default = "default_federation_timeout_secs")]
+    federation_timeout_secs: u64,
+    // bearer token for the admin api, disabled when unset
+    admin_token: Option<String>,
+    // file to keep bans in, they're lost on restart when unset
+    bans_path: Option<PathBuf>,
+    // secret join tickets are signed with, shared with game servers so they can verify them,
+    // reservations are disabled when unset
+    ticket_secret: Option<String>,
+    // how long a join ticket and the slot held for it last
+    #[serde(default = "default_ticket_ttl_secs")]
+    ticket_ttl_secs: u64,
+    // how long to wait for a game server to answer a reservation
+    #[serde(
+        default = "default_reservation_timeout_ms",
+        deserialize_with = "deserialize_reservation_timeout"
+    )]
+    reservation_timeout_ms: u64,
+    // file to save the list to so it survives restarts, disabled when unset
+    snapshot_path: Option<PathBuf>,
+    #[serde(
+        default = "default_snapshot_interval_secs",
+        deserialize_with = "deserialize_interval"
+    )]
+    snapshot_interval_secs: u64,
+    // how long to wait for connections to close when shutting down
+    #[serde(default = "default_shutdown_timeout_secs")]
+    shutdown_timeout_secs: u64,
+}
+
+#[derive(serde::Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+enum StoreBackend {
+    #[default]
+    Memory,
+    Redis,
+}
+
+fn default_bind_addr() -> IpAddr {
+    IpAddr::from([0, 0, 0, 0])
+}
+
+fn default_port() -> u16 {
+    3000
+}
+
+fn default_public_ip_required() -> bool {
+    true
 }
 
 fn default_ip_source() -> SecureClientIpSource {
     SecureClientIpSource::ConnectInfo
 }
 
+/// Deserializes an interval in seconds, which can't be zero as timers can't tick that often
+fn deserialize_interval<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<u64, D::Error> {
+    let secs = <u64 as serde::Deserialize>::deserialize(deserializer)?;
+    if secs == 0 {
+        return Err(serde::de::Error::custom(
+            "intervals must be at least 1 second",
+        ));
+    }
+    Ok(secs)
+}
+
+/// Deserializes the reservation timeout in milliseconds, which has to end before the request
+/// times out so clients hear why the reservation failed
+fn deserialize_reservation_timeout<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<u64, D::Error> {
+    let millis = <u64 as serde::Deserialize>::deserialize(deserializer)?;
+    if millis == 0 || u128::from(millis) >= REQUEST_TIMEOUT.as_millis() {
+        return Err(serde::de::Error::custom(format!(
+            "reservation timeout must be between 1 and {} milliseconds",
+            REQUEST_TIMEOUT.as_millis() - 1
+        )));
+    }
+    Ok(millis)
+}
+
+fn default_heartbeat_interval_secs() -> u64 {
+    15
+}
+
+fn default_idle_timeout_secs() -> u64 {
+    60
+}
+
+fn default_burst() -> u32 {
+    DEFAULT_BURST
+}
+
+fn default_probe_timeout_ms() -> u64 {
+    3000
+}
+
+fn default_probe_interval_secs() -> u64 {
+    60
+}
+
+fn default_resume_grace_secs() -> u64 {
+    30
+}
+
+fn default_name_min_length() -> usize {
+    1
+}
+
+fn default_name_max_length() -> usize {
+    64
+}
+
+fn default_quickjoin_fill_weight() -> f64 {
+    ScoringPolicy::default().fill_weight
+}
+
+fn default_quickjoin_official_weight() -> f64 {
+    ScoringPolicy::default().official_weight
+}
+
+fn default_redis_key() -> String {
+    String::from("gameserverlist:servers")
+}
+
+fn default_instance_name() -> String {
+    // the pod name in kubernetes
+    std::env::var("HOSTNAME").unwrap_or_else(|_| Uuid::new_v4().to_string())
+}
+
+fn default_federation_interval_secs() -> u64 {
+    5
+}
+
+fn default_federation_timeout_secs() -> u64 {
+    30
+}
+
+fn default_ticket_ttl_secs() -> u64 {
+    30
+}
+
+fn default_reservation_timeout_ms() -> u64 {
+    5000
+}
+
+fn default_snapshot_interval_secs() -> u64 {
+    30
+}
+
+fn default_shutdown_timeout_secs() -> u64 {
+    10
+}
+
+/// Instructions sent to a game server's connection from elsewhere in the list
+#[derive(Debug)]
+enum Control {
+    /// Another connection resumed the game server's entry
+    Replaced,
+    /// An admin removed the game server from the list, with the reason to close the connection with
+    Kick(String),
+    /// A client wants a slot held for it, the game server's answer is sent back
+    ReserveSlot {
+        reservation_id: Uuid,
+        expires_at: u64,
+        answer: oneshot::Sender<Answer>,
+    },
+}
+
+const CONTROL_CAPACITY: usize = 8;
+
+/// How long any HTTP request can take before it's answered with `408`
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Reservations for each game server by id, shared by its connections so held slots survive a
+/// resume
+type ReservationsById = Arc<Mutex<HashMap<Uuid, Arc<Mutex<Reservations>>>>>;
+
+/// A connected game server, for reaching it from elsewhere in the list
+struct Connection {
+    control: mpsc::Sender<Control>,
+    // the ip the game server connected from, which may not be the one it's listed under
+    ip: IpAddr,
+}
+
 // shared app state
 #[derive(Clone)]
 struct AppState {
-    server_list: ServerList,
-    server_ip: IpAddr,
+    // every game has its own list, by name
+    games: Arc<HashMap<String, Game>>,
+    // every connected game server, by id
+    connections: Arc<Mutex<HashMap<Uuid, Connection>>>,
+    reservations: ReservationsById,
+    bans: BanList,
+    bans_path: Option<Arc<PathBuf>>,
+    admin_token: Option<Arc<str>>,
+    tickets: Option<Arc<TicketSigner>>,
+    ticket_ttl: Duration,
+    reservation_timeout: Duration,
+    // public ip of the server list, if known
+    server_ip: Option<IpAddr>,
+    server_ipv6: Option<Ipv6Addr>,
+    address_ranges: Arc<AddressRanges>,
+    hostnames: Arc<HostnameVerifier<SystemResolver>>,
+    names: Arc<NamePolicy>,
+    scoring: ScoringPolicy,
+    // how often game servers are pinged
+    heartbeat_interval: Duration,
+    // game servers are evicted after sending nothing for this long
+    idle_timeout: Duration,
+    probe: Option<ProbeConfig>,
+    federation: Option<Arc<Federation>>,
+    // set to true once the server list starts shutting down
+    shutdown: watch::Receiver<bool>,
+    // dropped with the last clone of the app state, so main knows when connections have closed
+    _drain: mpsc::Sender<()>,
+}
+
+impl AppState {
+    // routes without a game in their path are for the default game
+    fn game(&self, game: Option<Path<String>>) -> Option<Game> {
+        let name = game
+            .as_ref()
+            .map_or(DEFAULT_GAME, |Path(name)| name.as_str());
+        self.games.get(name).cloned()
+    }
+    fn find_server(&self, server_id: &Uuid) -> Option<(Game, GameServer)> {
+        self.games
+            .values()
+            .find_map(|game| Some((game.clone(), game.server_list.server(server_id)?)))
+    }
 }
 
 #[tokio::main]
@@ -100,30 +481,252 @@ async fn main() {
 
     // get config from env
     let config: Config = envy::from_env().unwrap();
-    tracing::info!("env config: {:?}", config);
+    tracing::info!(
+        "env config: {:?}",
+        Config {
+            auth_tokens: vec![String::from("<redacted>"); config.auth_tokens.len()],
+            official_tokens: vec![String::from("<redacted>"); config.official_tokens.len()],
+            // the url can contain a password
+            redis_url: config
+                .redis_url
+                .as_ref()
+                .map(|_| String::from("<redacted>")),
+            federation_token: config
+                .federation_token
+                .as_ref()
+                .map(|_| String::from("<redacted>")),
+            admin_token: config
+                .admin_token
+                .as_ref()
+                .map(|_| String::from("<redacted>")),
+            ticket_secret: config
+                .ticket_secret
+                .as_ref()
+                .map(|_| String::from("<redacted>")),
+            ..config.clone()
+        }
+    );
 
     // determine server's public ip for local servers
-    let server_ip = match public_ip::addr().await {
+    let server_ip = match config.public_ip {
         Some(ip) => {
-            tracing::info!("found server's public ip: {}", ip);
-            ip
+            tracing::info!("using configured public ip: {}", ip);
+            Some(ip)
+        }
+        None => match public_ip::addr().await {
+            Some(ip) => {
+                tracing::info!("found server's public ip: {}", ip);
+                Some(ip)
+            }
+            None if config.public_ip_required => panic!("unable to find server's public ip address, please make sure it has a connection to the internet or set PUBLIC_IP"),
+            None => {
+                tracing::warn!("unable to find server's public ip address, local game servers will be listed with their own ip");
+                None
+            }
+        },
+    };
+
+    // each game gets its own list, with config falling back to the env config
+    let defaults = GameConfig {
+        auth_tokens: Some(config.auth_tokens.clone()),
+        official_tokens: Some(config.official_tokens.clone()),
+        auth_required: Some(config.auth_required),
+        max_servers_per_ip: config.max_servers_per_ip,
+        game_message_rate: config.game_message_rate,
+        game_message_burst: Some(config.game_message_burst),
+        list_request_rate: config.list_request_rate,
+        list_request_burst: Some(config.list_request_burst),
+        allowed_versions: Vec::new(),
+        version_rule: Some(config.version_rule),
+        min_version: config.min_game_version.clone(),
+    };
+    let mut game_configs = match &config.games_config {
+        Some(path) => {
+            let json = std::fs::read_to_string(path).expect("games config can be read");
+            parse_games(&json).expect("games config is valid")
         }
-        None => panic!("unable to find server's public ip address, please make sure it has a connection to the internet"),
+        None => HashMap::new(),
     };
+    game_configs.entry(String::from(DEFAULT_GAME)).or_default();
+    let games = game_configs
+        .into_iter()
+        .map(|(name, game_config)| {
+            let store: Arc<dyn ServerStore> = match config.store {
+                StoreBackend::Memory => Arc::new(MemoryStore::new()),
+                StoreBackend::Redis => {
+                    let url = config
+                        .redis_url
+                        .as_deref()
+                        .expect("REDIS_URL must be set to use the redis store");
+                    let key = match name.as_str() {
+                        DEFAULT_GAME => config.redis_key.clone(),
+                        name => format!("{}:{}", config.redis_key, name),
+                    };
+                    Arc::new(
+                        RedisStore::new(url, key, &config.instance_name)
+                            .expect("REDIS_URL is valid"),
+                    )
+                }
+            };
+            let server_list = ServerList::with_store(store);
+            let game = Game::new(name.clone(), server_list, &game_config, &defaults);
+            (name, game)
+        })
+        .collect::<HashMap<_, _>>();
+    tracing::info!("serving games: {:?}", games.keys().collect::<Vec<_>>());
 
+    let mut names = NamePolicy::new(
+        config.name_min_length,
+        config.name_max_length,
+        config.name_disallowed_chars.chars().collect(),
+    );
+    if let Some(path) = &config.name_blocklist_path {
+        let words = std::fs::read_to_string(path).expect("name blocklist can be read");
+        let filter = Arc::new(WordList::parse(&words));
+        names = names.with_filter(filter, config.name_filter_action);
+    }
+
+    // bans made through the admin api, or added to the file by hand
+    let bans = match &config.bans_path {
+        Some(path) => {
+            let bans = bans::load(path).await.expect("bans can be loaded");
+            tracing::info!("loaded {} bans from {:?}", bans.len(), path);
+            BanList::with_bans(bans)
+        }
+        None => BanList::new(),
+    };
+
+    let (shutdown_sender, shutdown) = watch::channel(false);
+    let (drain, mut drained) = mpsc::channel(1);
     let app_state = AppState {
-        server_list: ServerList::new(),
+        games: Arc::new(games),
+        connections: Arc::default(),
+        reservations: Arc::default(),
+        bans,
+        bans_path: config.bans_path.clone().map(Arc::new),
+        admin_token: config.admin_token.as_deref().map(Arc::from),
+        tickets: config
+            .ticket_secret
+            .as_deref()
+            .map(|secret| Arc::new(TicketSigner::new(secret))),
+        ticket_ttl: Duration::from_secs(config.ticket_ttl_secs),
+        reservation_timeout: Duration::from_millis(config.reservation_timeout_ms),
         server_ip,
+        server_ipv6: config.public_ipv6,
+        address_ranges: Arc::new(AddressRanges::new(
+            config.official_ranges.clone(),
+            config.rewrite_ranges.clone(),
+        )),
+        hostnames: Arc::new(HostnameVerifier::new(
+            SystemResolver,
+            config.hostname_allowlist.clone(),
+        )),
+        names: Arc::new(names),
+        scoring: ScoringPolicy {
+            fill_weight: config.quickjoin_fill_weight,
+            official_weight: config.quickjoin_official_weight,
+        },
+        heartbeat_interval: Duration::from_secs(config.heartbeat_interval_secs),
+        idle_timeout: Duration::from_secs(config.idle_timeout_secs),
+        probe: config.probe_enabled.then(|| ProbeConfig {
+            timeout: Duration::from_millis(config.probe_timeout_ms),
+            interval: Duration::from_secs(config.probe_interval_secs),
+        }),
+        federation: config
+            .federation_token
+            .clone()
+            .map(|token| Arc::new(Federation::new(config.instance_name.clone(), token))),
+        shutdown,
+        _drain: drain,
     };
 
+    for game in app_state.games.values() {
+        // restore the list from before the last restart, servers stay pending until they reconnect
+        if let Some(path) = &config.snapshot_path {
+            let path = snapshot_path(path, &game.name);
+            match snapshot::load(&path).await {
+                Ok(Some(snapshot)) => {
+                    let restored = game.server_list.clone().restore(snapshot);
+                    for server in &restored {
+                        IN_GAME_PLAYERS
+                            .with_label_values(&[&game.name])
+                            .add(i64::from(server.players));
+                    }
+                    tracing::info!("restored {} game servers from {:?}", restored.len(), path);
+                }
+                Ok(None) => tracing::info!("no snapshot to restore at {:?}", path),
+                Err(e) => tracing::error!("failed to restore snapshot from {:?}: {}", path, e),
+            }
+            tokio::spawn(save_snapshots(
+                game.server_list.clone(),
+                path,
+                Duration::from_secs(config.snapshot_interval_secs),
+            ));
+        }
+
+        // remove disconnected game servers once they can no longer resume
+        tokio::spawn(expire_servers(
+            game.clone(),
+            Duration::from_secs(config.resume_grace_secs),
+            app_state.reservations.clone(),
+        ));
+
+        // list the game servers connected to other instances
+        if let Some(token) = &config.federation_token {
+            let peers = config.federation_peers.iter().filter(|url| !url.is_empty());
+            for url in peers {
+                tracing::info!("following peer {} for {}", url, game.name);
+                let peer = Peer {
+                    url: url.clone(),
+                    game: game.name.clone(),
+                    token: token.clone(),
+                    interval: Duration::from_secs(config.federation_interval_secs),
+                    timeout: Duration::from_secs(config.federation_timeout_secs),
+                };
+                tokio::spawn(follow_peer(
+                    game.server_list.clone(),
+                    peer,
+                    config.instance_name.clone(),
+                ));
+            }
+        }
+    }
+
+    // only reachable with the admin token
+    let admin = Router::new()
+        .route("/servers", get(admin_servers))
+        .route("/servers/:id", delete(kick_server))
+        .route(
+            "/servers/:id/featured",
+            put(feature_server).delete(unfeature_server),
+        )
+        .route("/bans", get(list_bans).post(add_ban))
+        .route("/bans/:id", delete(remove_ban))
+        .route_layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            require_admin,
+        ));
+
     // build our application with some routes
     let app = Router::new()
         .route("/api/list/healthcheck", get(healthcheck))
         .route("/api/list/servers", get(get_servers))
         // websocket route
         .route("/api/list/ws", get(websocket_handler))
-        // keep metrics on root so proxy doesn't expose it
+        .route("/api/list/subscribe", get(subscribe_handler))
+        .route("/api/list/quickjoin", get(quick_join))
+        .route("/api/list/reserve", post(reserve_slot))
+        // the same routes for each game
+        .route("/api/list/:game/servers", get(get_servers))
+        .route("/api/list/:game/ws", get(websocket_handler))
+        .route("/api/list/:game/subscribe", get(subscribe_handler))
+        .route("/api/list/:game/quickjoin", get(quick_join))
+        .route("/api/list/:game/reserve", post(reserve_slot))
+        // keep metrics and peer endpoints on root so proxy doesn't expose them
         .route("/metrics", get(get_metrics))
+        .route("/internal/servers", get(get_local_servers))
+        .route("/internal/:game/servers", get(get_local_servers))
+        .nest("/admin", admin)
         // determine the secure ip source from the env
         .layer(config.ip_source.into_extension())
         // add default services for error handling, timeout and tracing
@@ -139,19 +742,81 @@ async fn main() {
                         ))
                     }
                 }))
-                .timeout(Duration::from_secs(10))
+                .timeout(REQUEST_TIMEOUT)
                 .layer(TraceLayer::new_for_http())
                 .into_inner(),
         )
-        .with_state(app_state);
+        .with_state(app_state.clone());
+    // keep the lists for the final snapshot without holding up connection draining
+    let games = app_state.games.clone();
+    drop(app_state);
 
     // run the server
-    let addr = SocketAddr::from(([0, 0, 0, 0], 3000));
+    let addr = SocketAddr::from((config.bind_addr, config.port));
     tracing::info!("listening on {}", addr);
-    axum::Server::bind(&addr)
+    axum::Server::from_tcp(bind(addr).unwrap())
+        .unwrap()
         .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(async move {
+            shutdown_signal().await;
+            tracing::info!("shutting down, closing connections");
+            // tells every connection to close and stops new registrations
+            shutdown_sender.send_replace(true);
+        })
         .await
         .unwrap();
+
+    // wait for websocket connections to finish closing
+    let shutdown_timeout = Duration::from_secs(config.shutdown_timeout_secs);
+    if tokio::time::timeout(shutdown_timeout, drained.recv())
+        .await
+        .is_err()
+    {
+        tracing::warn!("timed out waiting for connections to close");
+    }
+    if let Some(path) = &config.snapshot_path {
+        for game in games.values() {
+            save_snapshot(&game.server_list, &snapshot_path(path, &game.name)).await;
+        }
+    }
+    tracing::info!("final metrics:\n{}", encode_metrics());
+}
+
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to install signal handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
+/// Binds the listener, accepting both IPv4 and IPv6 connections when bound to `::`
+fn bind(addr: SocketAddr) -> std::io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(false)?;
+    }
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    Ok(socket.into())
 }
 
 // Added for Docker healthcheck to ensure server still responding
@@ -162,18 +827,364 @@ async fn healthcheck() -> &'static str {
 /// Returns the server list with all games on it
 #[instrument(skip(app_state))]
 async fn get_servers(
-    pagination: Option<Query<Pagination>>,
+    game: Option<Path<String>>,
+    query: Option<Query<ServerQuery>>,
     SecureClientIp(ip): SecureClientIp,
     State(app_state): State<AppState>,
 ) -> impl IntoResponse {
+    let Some(game) = app_state.game(game) else {
+        return (StatusCode::NOT_FOUND, "unknown game").into_response();
+    };
+    if let Some(limiter) = &game.list_request_limiter {
+        if !limiter.check(ip) {
+            tracing::warn!("rate limited server list request");
+            RATE_LIMITED
+                .with_label_values(&[&game.name, "list_requests"])
+                .inc();
+            return StatusCode::TOO_MANY_REQUESTS.into_response();
+        }
+    }
     tracing::info!("sending server list");
-    SERVER_LIST_REQUESTS.inc();
-    let Query(pagination) = pagination.unwrap_or_default();
-    Json(app_state.server_list.get(&pagination))
+    SERVER_LIST_REQUESTS.with_label_values(&[&game.name]).inc();
+    let Query(query) = query.unwrap_or_default();
+    if query.cursor.is_none() {
+        return Json(game.server_list.get(&query)).into_response();
+    }
+    match game.server_list.page(&query) {
+        Ok(page) => Json(page).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+/// Returns how to connect to the best server for the client to join
+#[instrument(skip(app_state))]
+async fn quick_join(
+    game: Option<Path<String>>,
+    query: Option<Query<QuickJoinQuery>>,
+    SecureClientIp(ip): SecureClientIp,
+    State(app_state): State<AppState>,
+) -> impl IntoResponse {
+    let Some(game) = app_state.game(game) else {
+        return (StatusCode::NOT_FOUND, "unknown game").into_response();
+    };
+    if let Some(limiter) = &game.list_request_limiter {
+        if !limiter.check(ip) {
+            tracing::warn!("rate limited quick join request");
+            RATE_LIMITED
+                .with_label_values(&[&game.name, "list_requests"])
+                .inc();
+            return StatusCode::TOO_MANY_REQUESTS.into_response();
+        }
+    }
+    SERVER_LIST_REQUESTS.with_label_values(&[&game.name]).inc();
+    let Query(query) = query.unwrap_or_default();
+    match game.server_list.quick_join(&query, &app_state.scoring) {
+        Some(server) => Json(ConnectionDetails::from(&server)).into_response(),
+        None => (StatusCode::NOT_FOUND, "no server to join").into_response(),
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct ReserveRequest {
+    server_id: Uuid,
+}
+
+/// What a client presents to the game server to join with the slot held for it
+#[derive(serde::Serialize)]
+struct JoinTicket {
+    ticket: String,
+    reservation_id: Uuid,
+    expires_at: u64,
+    server: ConnectionDetails,
+}
+
+/// Asks the game server to hold a slot for the client, returning a ticket to join it with
+#[instrument(skip(app_state))]
+async fn reserve_slot(
+    game: Option<Path<String>>,
+    SecureClientIp(ip): SecureClientIp,
+    State(app_state): State<AppState>,
+    Json(request): Json<ReserveRequest>,
+) -> Response {
+    let (Some(tickets), Some(game)) = (&app_state.tickets, app_state.game(game)) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if let Some(limiter) = &game.list_request_limiter {
+        if !limiter.check(ip) {
+            tracing::warn!("rate limited reservation request");
+            RATE_LIMITED
+                .with_label_values(&[&game.name, "list_requests"])
+                .inc();
+            return StatusCode::TOO_MANY_REQUESTS.into_response();
+        }
+    }
+    let Some(server) = game.server_list.server(&request.server_id) else {
+        return (StatusCode::NOT_FOUND, "unknown game server").into_response();
+    };
+    if server.origin().is_some() {
+        return (StatusCode::CONFLICT, "game server is listed from a peer").into_response();
+    }
+    // hidden servers aren't listed, so clients shouldn't be able to find them this way either
+    if !server.is_visible() {
+        return (StatusCode::NOT_FOUND, "unknown game server").into_response();
+    }
+    if server.status() != ServerStatus::Online {
+        return (StatusCode::CONFLICT, "game server is not connected").into_response();
+    }
+    if server.metadata.password_protected {
+        return (StatusCode::CONFLICT, "game server is password protected").into_response();
+    }
+    if server.is_full() {
+        return (StatusCode::CONFLICT, "game server is full").into_response();
+    }
+    let connection = app_state
+        .connections
+        .lock()
+        .unwrap()
+        .get(&request.server_id)
+        .map(|connection| connection.control.clone());
+    let Some(control) = connection else {
+        return (StatusCode::CONFLICT, "game server is not connected").into_response();
+    };
+    let claims = TicketClaims::new(server.id(), app_state.ticket_ttl);
+    let (answer, answered) = oneshot::channel();
+    let reserve = Control::ReserveSlot {
+        reservation_id: claims.reservation_id,
+        expires_at: claims.expires_at,
+        answer,
+    };
+    if control.try_send(reserve).is_err() {
+        return (StatusCode::SERVICE_UNAVAILABLE, "game server is busy").into_response();
+    }
+    match tokio::time::timeout(app_state.reservation_timeout, answered).await {
+        Ok(Ok(Ok(()))) => {}
+        Ok(Ok(Err(reason))) => return (StatusCode::CONFLICT, reason).into_response(),
+        // the connection closed before the game server answered
+        Ok(Err(_)) => return (StatusCode::CONFLICT, "game server disconnected").into_response(),
+        Err(_) => {
+            tracing::warn!("game server {} didn't answer reservation", server.id());
+            return (StatusCode::GATEWAY_TIMEOUT, "game server didn't answer").into_response();
+        }
+    }
+    tracing::info!("reserved a slot on game server {}", server.id());
+    Json(JoinTicket {
+        ticket: tickets.sign(&claims),
+        reservation_id: claims.reservation_id,
+        expires_at: claims.expires_at,
+        server: ConnectionDetails::from(&server),
+    })
+    .into_response()
+}
+
+/// Returns the game servers connected to this instance for peers to list
+async fn get_local_servers(
+    game: Option<Path<String>>,
+    authorization: Option<TypedHeader<Authorization<Bearer>>>,
+    State(app_state): State<AppState>,
+) -> impl IntoResponse {
+    let (Some(federation), Some(game)) = (&app_state.federation, app_state.game(game)) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let token = authorization.as_ref().map(|TypedHeader(auth)| auth.token());
+    if !federation.authorize(token) {
+        tracing::warn!("rejected peer with invalid token");
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    Json(PeerList {
+        instance: federation.instance().to_string(),
+        servers: game.server_list.local(),
+    })
+    .into_response()
+}
+
+/// Rejects admin requests without the admin token, hiding the admin api when it's disabled
+async fn require_admin<B>(
+    State(app_state): State<AppState>,
+    authorization: Option<TypedHeader<Authorization<Bearer>>>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let Some(admin_token) = &app_state.admin_token else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let token = authorization.as_ref().map(|TypedHeader(auth)| auth.token());
+    if !token.is_some_and(|token| constant_time_eq(admin_token, token)) {
+        tracing::warn!("rejected admin request with invalid token");
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    next.run(request).await
+}
+
+/// A game server as admins see it
+#[derive(serde::Serialize)]
+struct AdminServer {
+    game: String,
+    // unknown unless the game server is connected to this instance
+    source_ip: Option<IpAddr>,
+    #[serde(flatten)]
+    server: GameServer,
+}
+
+/// Returns every game server including hidden ones, with the IP each connected from
+async fn admin_servers(State(app_state): State<AppState>) -> Json<Vec<AdminServer>> {
+    let mut games = app_state.games.values().collect::<Vec<_>>();
+    games.sort_by(|a, b| a.name.cmp(&b.name));
+    let connections = app_state.connections.lock().unwrap();
+    let servers = games
+        .into_iter()
+        .flat_map(|game| {
+            game.server_list
+                .all()
+                .into_iter()
+                .map(|server| AdminServer {
+                    game: game.name.clone(),
+                    source_ip: connections
+                        .get(&server.id())
+                        .map(|connection| connection.ip),
+                    server,
+                })
+        })
+        .collect();
+    Json(servers)
+}
+
+async fn kick_server(
+    Path(server_id): Path<Uuid>,
+    State(app_state): State<AppState>,
+) -> impl IntoResponse {
+    match kick(&app_state, &server_id, "kicked by an admin") {
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Removes the game server from the list and closes its connection, it can't resume its entry
+fn kick(
+    app_state: &AppState,
+    server_id: &Uuid,
+    reason: &str,
+) -> Result<GameServer, (StatusCode, &'static str)> {
+    let (game, server) = app_state
+        .find_server(server_id)
+        .ok_or((StatusCode::NOT_FOUND, "unknown game server"))?;
+    if server.origin().is_some() {
+        return Err((StatusCode::CONFLICT, "game server is listed from a peer"));
+    }
+    if let Some(removed) = game.server_list.clone().remove(server_id) {
+        IN_GAME_PLAYERS
+            .with_label_values(&[&game.name])
+            .sub(i64::from(removed.players));
+    }
+    let connection = app_state.connections.lock().unwrap().remove(server_id);
+    app_state.reservations.lock().unwrap().remove(server_id);
+    if let Some(connection) = connection {
+        let _ = connection
+            .control
+            .try_send(Control::Kick(String::from(reason)));
+    }
+    tracing::info!("kicked game server {}: {}", server_id, reason);
+    Ok(server)
+}
+
+async fn feature_server(
+    Path(server_id): Path<Uuid>,
+    State(app_state): State<AppState>,
+) -> impl IntoResponse {
+    set_featured(&app_state, &server_id, true)
+}
+
+async fn unfeature_server(
+    Path(server_id): Path<Uuid>,
+    State(app_state): State<AppState>,
+) -> impl IntoResponse {
+    set_featured(&app_state, &server_id, false)
+}
+
+fn set_featured(app_state: &AppState, server_id: &Uuid, featured: bool) -> Response {
+    let Some((game, server)) = app_state.find_server(server_id) else {
+        return (StatusCode::NOT_FOUND, "unknown game server").into_response();
+    };
+    // the peer's copy would replace it on the next sync
+    if server.origin().is_some() {
+        return (StatusCode::CONFLICT, "game server is listed from a peer").into_response();
+    }
+    game.server_list.set_featured(server_id, featured);
+    tracing::info!("set game server {} featured: {}", server_id, featured);
+    StatusCode::NO_CONTENT.into_response()
+}
+
+#[derive(serde::Deserialize)]
+struct NewBan {
+    #[serde(flatten)]
+    target: BanTarget,
+    // the ban never ends when unset
+    duration_secs: Option<u64>,
+    reason: Option<String>,
+}
+
+async fn list_bans(State(app_state): State<AppState>) -> impl IntoResponse {
+    Json(app_state.bans.active())
+}
+
+/// Bans game servers from registering, kicking any already connected
+async fn add_ban(State(app_state): State<AppState>, Json(new_ban): Json<NewBan>) -> Response {
+    let duration = new_ban.duration_secs.map(Duration::from_secs);
+    if duration.is_some_and(|duration| duration > bans::MAX_DURATION) {
+        let message = format!(
+            "duration_secs must be at most {}, leave it out for a ban that never ends",
+            bans::MAX_DURATION.as_secs()
+        );
+        return (StatusCode::BAD_REQUEST, message).into_response();
+    }
+    let ban = app_state.bans.add(new_ban.target, duration, new_ban.reason);
+    tracing::info!("added ban: {:?}", ban);
+    save_bans(&app_state).await;
+    let banned = {
+        let connections = app_state.connections.lock().unwrap();
+        connections
+            .iter()
+            .filter(|(server_id, connection)| {
+                app_state
+                    .find_server(server_id)
+                    .is_some_and(|(_, server)| ban.target.matches(connection.ip, server.name()))
+            })
+            .map(|(server_id, _)| *server_id)
+            .collect::<Vec<_>>()
+    };
+    for server_id in banned {
+        let _ = kick(&app_state, &server_id, &ban.message());
+    }
+    (StatusCode::CREATED, Json(ban)).into_response()
+}
+
+async fn remove_ban(
+    Path(ban_id): Path<Uuid>,
+    State(app_state): State<AppState>,
+) -> impl IntoResponse {
+    match app_state.bans.remove(&ban_id) {
+        Some(ban) => {
+            tracing::info!("removed ban: {:?}", ban);
+            save_bans(&app_state).await;
+            StatusCode::NO_CONTENT
+        }
+        None => StatusCode::NOT_FOUND,
+    }
+}
+
+async fn save_bans(app_state: &AppState) {
+    if let Some(path) = &app_state.bans_path {
+        if let Err(e) = bans::save(path, &app_state.bans.active()).await {
+            tracing::error!("failed to save bans to {:?}: {}", path, e);
+        }
+    }
 }
 
 /// Returns prometheus metrics
 async fn get_metrics() -> impl IntoResponse {
+    encode_metrics()
+}
+
+fn encode_metrics() -> String {
     use prometheus::Encoder;
     let encoder = prometheus::TextEncoder::new();
 
@@ -206,46 +1217,169 @@ async fn get_metrics() -> impl IntoResponse {
     buffer.clear();
 
     res.push_str(&res_custom);
-    return res;
+    res
 }
 
-#[instrument(level = "debug", skip(ws, app_state))]
+#[instrument(level = "debug", skip(ws, authorization, app_state))]
 async fn websocket_handler(
     ws: WebSocketUpgrade,
+    game: Option<Path<String>>,
     SecureClientIp(ip): SecureClientIp,
+    authorization: Option<TypedHeader<Authorization<Bearer>>>,
     State(app_state): State<AppState>,
 ) -> impl IntoResponse {
+    if *app_state.shutdown.borrow() {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "server list is shutting down",
+        )
+            .into_response();
+    }
+    let Some(game) = app_state.game(game) else {
+        return (StatusCode::NOT_FOUND, "unknown game").into_response();
+    };
     tracing::info!("new websocket connection");
-    ws.protocols(["json"]).on_upgrade(move |socket| {
-        handle_socket(socket, ip, app_state.server_list, app_state.server_ip)
-    })
+    let ip = ip.to_canonical();
+    // upgraded anyway so the game server finds out why in the close frame
+    if let Some(ban) = app_state.bans.find_ip(ip) {
+        tracing::warn!("rejected banned IP {}: {:?}", ip, ban);
+        let error = ListError::new(ErrorCode::Banned, ban.message());
+        return ws
+            .protocols(["json"])
+            .on_upgrade(move |socket| reject(socket, error))
+            .into_response();
+    }
+    let token = authorization.map(|TypedHeader(auth)| auth.token().to_string());
+    ws.protocols(["json"])
+        .on_upgrade(move |socket| handle_socket(socket, ip, token, game, app_state))
+        .into_response()
 }
 
-#[instrument(level = "debug", name = "websocket_handler", skip(socket, server_list))]
+#[instrument(
+    level = "debug",
+    name = "websocket_handler",
+    skip(socket, header_token, game, app_state),
+    fields(game = %game.name)
+)]
 async fn handle_socket(
     mut socket: WebSocket,
     ip: IpAddr,
-    mut server_list: ServerList,
-    server_ip: IpAddr,
+    header_token: Option<String>,
+    game: Game,
+    app_state: AppState,
 ) {
-    let game_id;
+    let mut server_list = game.server_list.clone();
+    let mut shutdown = app_state.shutdown.clone();
+    let registration;
+    let (probe_sender, mut probe_results) = mpsc::channel(1);
+    // room for a few reservations without kicks failing to get through
+    let (control_sender, mut control) = mpsc::channel(CONTROL_CAPACITY);
 
     // wait for the first message with initial server info
-    match socket.recv().await {
+    let first_message = match tokio::time::timeout(app_state.idle_timeout, socket.recv()).await {
+        Ok(first_message) => first_message,
+        Err(_) => {
+            tracing::warn!("timed out while waiting for server info");
+            return;
+        }
+    };
+    match first_message {
         Some(result) => match result {
             Ok(msg) => match msg {
-                Message::Text(txt) => match parse_connect_message(txt, ip, server_ip) {
-                    Ok(server) => {
-                        tracing::info!("created new game server: {:?}", server);
-                        game_id = server_list.add(server);
-                        // add server to metrics
-                        CONNECTED_GAME_SERVERS.inc();
-                    }
-                    Err(e) => {
-                        tracing::error!("{:?}", e);
-                        return;
+                Message::Text(_) if *shutdown.borrow() => {
+                    let error =
+                        ListError::new(ErrorCode::ShuttingDown, "server list is shutting down");
+                    reject(socket, error).await;
+                    return;
+                }
+                Message::Text(txt) => {
+                    // taken before registering, which can wait on DNS, so servers connecting at
+                    // the same time can't all get past the limit
+                    let ip_slot = match game.max_servers_per_ip {
+                        Some(max) => match take_ip_slot(&txt, ip, max, &game) {
+                            Some(ip_slot) => Some(ip_slot),
+                            None => {
+                                tracing::warn!("too many game servers registered from {}", ip);
+                                RATE_LIMITED
+                                    .with_label_values(&[&game.name, "servers_per_ip"])
+                                    .inc();
+                                let error = ListError::new(
+                                    ErrorCode::TooManyServers,
+                                    format!(
+                                        "at most {} game servers can register from one IP",
+                                        max
+                                    ),
+                                );
+                                reject(socket, error).await;
+                                return;
+                            }
+                        },
+                        None => None,
+                    };
+                    match register(txt, ip, header_token, &game, &app_state).await {
+                        Ok((mut server, resume_token)) => {
+                            if let Some(probe) = app_state.probe {
+                                // hidden until the first probe succeeds, a resumed entry keeps its
+                                // reachability until then
+                                server.reachable = Some(false);
+                                let addr = SocketAddr::new(server.ip(), server.port());
+                                tokio::spawn(probe_loop(addr, probe, probe_sender));
+                            }
+                            tracing::info!("created new game server: {:?}", server);
+                            let (public_ip, official, community, name, flagged) = (
+                                server.ip(),
+                                server.official(),
+                                server.community(),
+                                server.name().to_string(),
+                                server.flagged(),
+                            );
+                            registration = server_list.connect(server, resume_token.as_deref());
+                            // the entry stays listed while it can be resumed, so it keeps the slot
+                            // until it's removed rather than until this connection closes
+                            if let Some(ip_slot) = ip_slot {
+                                server_list.hold_ip_slot(&registration, ip_slot);
+                            }
+                            if registration.resumed {
+                                tracing::info!("resumed game server: {}", registration.id);
+                            }
+                            let connection = Connection {
+                                control: control_sender.clone(),
+                                ip,
+                            };
+                            let previous = app_state
+                                .connections
+                                .lock()
+                                .unwrap()
+                                .insert(registration.id, connection);
+                            if let Some(previous) = previous {
+                                // the old connection may not have noticed it dropped yet
+                                let _ = previous.control.try_send(Control::Replaced);
+                            }
+                            // add server to metrics
+                            CONNECTED_GAME_SERVERS
+                                .with_label_values(&[&game.name])
+                                .inc();
+                            let registered = ListMessage::Registered {
+                                id: registration.id,
+                                public_ip,
+                                official,
+                                community,
+                                resume_token: registration.resume_token.clone(),
+                                resumed: registration.resumed,
+                                name,
+                                flagged,
+                            };
+                            if let Err(e) = send_message(&mut socket, &registered).await {
+                                tracing::error!("failed to acknowledge registration: {:?}", e);
+                            }
+                        }
+                        Err(e) => {
+                            tracing::error!("{:?}", e);
+                            reject(socket, e).await;
+                            return;
+                        }
                     }
-                },
+                }
                 Message::Close(_) => {
                     tracing::info!("connection closed while waiting for server info");
                     return;
@@ -255,6 +1389,11 @@ async fn handle_socket(
                         "got invalid message type while waiting for server info: {:?}",
                         msg
                     );
+                    let error = ListError::new(
+                        ErrorCode::InvalidMessageType,
+                        "expected a text message with server info",
+                    );
+                    reject(socket, error).await;
                     return;
                 }
             },
@@ -269,111 +1408,565 @@ async fn handle_socket(
         }
     }
     // begin the main loop to update the game server state
+    let mut heartbeat = tokio::time::interval(app_state.heartbeat_interval);
+    let mut last_seen = Instant::now();
+    // the list's last_seen is only updated on heartbeats, rather than for every frame
+    let mut last_touched = last_seen;
+    let mut message_limit = game
+        .game_message_limit
+        .map(|(rate, burst)| TokenBucket::new(rate, burst));
+    let game_id = registration.id;
+    // taken over from the connection this one resumed, if it held any slots
+    let reservations = app_state
+        .reservations
+        .lock()
+        .unwrap()
+        .entry(game_id)
+        .or_default()
+        .clone();
+    let reserved = reservations.lock().unwrap().held();
+    server_list.update(&game_id, |game_server| game_server.reserved = reserved);
     loop {
-        if let Some(msg_type) = socket.recv().await {
-            match msg_type {
-                Ok(msg) => match msg {
-                    Message::Text(t) => parse_game_message(&server_list, &game_id, &t),
-                    Message::Close(_) => {
-                        tracing::debug!("connection closed");
-                        break;
+        let next_expiry = reservations.lock().unwrap().next_expiry();
+        tokio::select! {
+            msg_type = socket.recv() => {
+                if let Some(msg_type) = msg_type {
+                    match msg_type {
+                        Ok(msg) => {
+                            last_seen = Instant::now();
+                            match msg {
+                                Message::Text(_)
+                                    if message_limit.as_mut().is_some_and(|limit| !limit.try_take()) =>
+                                {
+                                    RATE_LIMITED
+                                        .with_label_values(&[&game.name, "game_messages"])
+                                        .inc();
+                                    let error = ListError::new(
+                                        ErrorCode::RateLimited,
+                                        "too many game messages, this one was ignored",
+                                    );
+                                    if let Err(e) = send_message(&mut socket, &error.into()).await {
+                                        tracing::error!("failed to send error: {:?}", e);
+                                        break;
+                                    }
+                                }
+                                Message::Text(t) => {
+                                    let parsed = parse_game_message(
+                                        &game,
+                                        &game_id,
+                                        &t,
+                                        &mut reservations.lock().unwrap(),
+                                    );
+                                    if let Err(e) = parsed {
+                                        tracing::error!("{:?}", e);
+                                        if let Err(e) = send_message(&mut socket, &e.into()).await {
+                                            tracing::error!("failed to send error: {:?}", e);
+                                            break;
+                                        }
+                                    }
+                                }
+                                // pings are answered automatically, pongs only count as activity
+                                Message::Ping(_) | Message::Pong(_) => {}
+                                Message::Close(_) => {
+                                    tracing::debug!("connection closed");
+                                    break;
+                                }
+                                _ => {
+                                    tracing::warn!("got invalid message type: {:?}", msg);
+                                    let error = ListError::new(
+                                        ErrorCode::InvalidMessageType,
+                                        "game messages must be sent as text",
+                                    );
+                                    if let Err(e) = send_message(&mut socket, &error.into()).await {
+                                        tracing::error!("failed to send error: {:?}", e);
+                                        break;
+                                    }
+                                }
+                            }
+                        }
+                        Err(e) => {
+                            tracing::error!("error while waiting for game message: {:?}", e);
+                            break;
+                        }
+                    }
+                } else {
+                    tracing::warn!("connection closed unexpectedly");
+                    break;
+                }
+            }
+            Some(reachable) = probe_results.recv() => {
+                tracing::info!("game server reachable: {}", reachable);
+                server_list.update(&game_id, |game_server| game_server.reachable = Some(reachable));
+                let msg = ListMessage::Reachability { reachable };
+                if let Err(e) = send_message(&mut socket, &msg).await {
+                    tracing::error!("failed to send reachability: {:?}", e);
+                    break;
+                }
+            }
+            Some(instruction) = control.recv() => {
+                let frame = match instruction {
+                    Control::ReserveSlot { reservation_id, expires_at, answer } => {
+                        let expires = Instant::now() + app_state.ticket_ttl;
+                        reservations.lock().unwrap().request(reservation_id, expires, answer);
+                        let msg = ListMessage::ReserveSlot { reservation_id, expires_at };
+                        if let Err(e) = send_message(&mut socket, &msg).await {
+                            tracing::error!("failed to send reservation: {:?}", e);
+                            break;
+                        }
+                        continue;
                     }
-                    _ => {
-                        tracing::warn!("got invalid message type: {:?}", msg)
+                    Control::Replaced => {
+                        tracing::info!("game server resumed by another connection");
+                        CloseFrame {
+                            code: close_code::NORMAL,
+                            reason: "resumed by another connection".into(),
+                        }
                     }
-                },
-                Err(e) => {
-                    tracing::error!("error while waiting for game message: {:?}", e);
+                    Control::Kick(reason) => {
+                        tracing::info!("game server kicked: {}", reason);
+                        CloseFrame {
+                            code: close_code::POLICY,
+                            reason: reason.into(),
+                        }
+                    }
+                };
+                if let Err(e) = socket.send(Message::Close(Some(frame))).await {
+                    tracing::debug!("failed to close connection: {:?}", e);
+                }
+                break;
+            }
+            _ = tokio::time::sleep_until(next_expiry.unwrap_or_else(Instant::now).into()),
+                if next_expiry.is_some() =>
+            {
+                let expired = {
+                    let mut reservations = reservations.lock().unwrap();
+                    reservations
+                        .remove_expired(Instant::now())
+                        .then(|| reservations.held())
+                };
+                if let Some(reserved) = expired {
+                    server_list.update(&game_id, |game_server| game_server.reserved = reserved);
+                }
+            }
+            _ = shutdown.changed() => {
+                tracing::info!("closing game server connection for shutdown");
+                close_for_shutdown(&mut socket).await;
+                break;
+            }
+            _ = heartbeat.tick() => {
+                if last_seen.elapsed() > app_state.idle_timeout {
+                    tracing::warn!("evicting game server idle for {:?}", last_seen.elapsed());
+                    break;
+                }
+                if last_seen > last_touched {
+                    server_list.touch(&game_id);
+                    last_touched = last_seen;
+                }
+                if let Err(e) = socket.send(Message::Ping(Vec::new())).await {
+                    tracing::error!("failed to ping game server: {:?}", e);
+                    break;
+                }
+            }
+        }
+    }
+    // Make sure server is always disconnected if the loop finishes
+    {
+        let mut connections = app_state.connections.lock().unwrap();
+        if connections
+            .get(&game_id)
+            .is_some_and(|connection| connection.control.same_channel(&control_sender))
+        {
+            connections.remove(&game_id);
+            // slots still held are kept for the connection which resumes the game server
+            let mut by_id = app_state.reservations.lock().unwrap();
+            let mut reservations = reservations.lock().unwrap();
+            reservations.cancel_pending();
+            if reservations.held() == 0 {
+                by_id.remove(&game_id);
+            }
+        }
+    }
+    if server_list.disconnect(&registration) {
+        tracing::info!("game server disconnected: {}", game_id);
+    }
+    // remove connection from metrics
+    CONNECTED_GAME_SERVERS
+        .with_label_values(&[&game.name])
+        .dec();
+}
+
+#[instrument(level = "debug", skip(ws, app_state))]
+async fn subscribe_handler(
+    ws: WebSocketUpgrade,
+    game: Option<Path<String>>,
+    SecureClientIp(ip): SecureClientIp,
+    State(app_state): State<AppState>,
+) -> impl IntoResponse {
+    let Some(game) = app_state.game(game) else {
+        return (StatusCode::NOT_FOUND, "unknown game").into_response();
+    };
+    tracing::info!("new list subscriber");
+    ws.protocols(["json"])
+        .on_upgrade(move |socket| handle_subscriber(socket, game, app_state))
+        .into_response()
+}
+
+#[instrument(
+    level = "debug",
+    name = "subscribe_handler",
+    skip(socket, game, app_state),
+    fields(game = %game.name)
+)]
+async fn handle_subscriber(mut socket: WebSocket, game: Game, app_state: AppState) {
+    LIST_SUBSCRIBERS.with_label_values(&[&game.name]).inc();
+    let mut shutdown = app_state.shutdown.clone();
+    let (servers, mut events) = game.server_list.subscribe();
+    let mut next_event = Some(ServerEvent::Snapshot { servers });
+    let mut heartbeat = tokio::time::interval(app_state.heartbeat_interval);
+    loop {
+        if let Some(event) = next_event.take() {
+            let txt = serde_json::to_string(&event).expect("server events can be serialized");
+            if let Err(e) = socket.send(Message::Text(txt)).await {
+                tracing::debug!("failed to send event to subscriber: {:?}", e);
+                break;
+            }
+        }
+        tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) => next_event = Some(event),
+                Err(RecvError::Lagged(missed)) => {
+                    // start again from a fresh snapshot rather than skipping changes
+                    tracing::warn!("subscriber missed {} events, resending snapshot", missed);
+                    let (servers, resubscribed) = game.server_list.subscribe();
+                    events = resubscribed;
+                    next_event = Some(ServerEvent::Snapshot { servers });
+                }
+                Err(RecvError::Closed) => break,
+            },
+            msg = socket.recv() => match msg {
+                Some(Ok(Message::Close(_))) | None => {
+                    tracing::debug!("subscriber disconnected");
+                    break;
+                }
+                Some(Err(e)) => {
+                    tracing::debug!("error while waiting for subscriber: {:?}", e);
+                    break;
+                }
+                // subscribers have nothing to say, anything else is ignored
+                Some(Ok(_)) => {}
+            },
+            _ = shutdown.changed() => {
+                close_for_shutdown(&mut socket).await;
+                break;
+            }
+            _ = heartbeat.tick() => {
+                if let Err(e) = socket.send(Message::Ping(Vec::new())).await {
+                    tracing::debug!("failed to ping subscriber: {:?}", e);
                     break;
                 }
             }
-        } else {
-            tracing::warn!("connection closed unexpectedly");
-            break;
         }
     }
-    // Make sure server is always removed if the loop finishes
-    remove_server(server_list, &game_id);
+    LIST_SUBSCRIBERS.with_label_values(&[&game.name]).dec();
+}
+
+async fn send_message(socket: &mut WebSocket, msg: &ListMessage) -> Result<(), axum::Error> {
+    let txt = serde_json::to_string(msg).expect("list messages can be serialized");
+    socket.send(Message::Text(txt)).await
+}
+
+/// Asks the other side to reconnect once the server list is back up
+async fn close_for_shutdown(socket: &mut WebSocket) {
+    let frame = CloseFrame {
+        code: close_code::RESTART,
+        reason: "list restarting, reconnect".into(),
+    };
+    if let Err(e) = socket.send(Message::Close(Some(frame))).await {
+        tracing::debug!("failed to close connection for shutdown: {:?}", e);
+    }
+}
+
+/// Reports the error to the game server then closes the connection
+async fn reject(mut socket: WebSocket, error: ListError) {
+    let code = match error.code {
+        ErrorCode::InvalidConnectMessage | ErrorCode::InvalidGameMessage => close_code::INVALID,
+        ErrorCode::InvalidMessageType => close_code::UNSUPPORTED,
+        ErrorCode::Unauthorized
+        | ErrorCode::InvalidHostname
+        | ErrorCode::UnsupportedVersion
+        | ErrorCode::Banned => close_code::POLICY,
+        ErrorCode::InvalidName => close_code::INVALID,
+        ErrorCode::TooManyServers | ErrorCode::RateLimited => close_code::AGAIN,
+        ErrorCode::ShuttingDown => close_code::RESTART,
+    };
+    // close frame reasons are limited to 123 bytes
+    let mut reason = error.message.clone();
+    if reason.len() > 123 {
+        let mut end = 123;
+        while !reason.is_char_boundary(end) {
+            end -= 1;
+        }
+        reason.truncate(end);
+    }
+    if let Err(e) = send_message(&mut socket, &error.into()).await {
+        tracing::error!("failed to send error: {:?}", e);
+        return;
+    }
+    let frame = CloseFrame {
+        code,
+        reason: reason.into(),
+    };
+    if let Err(e) = socket.send(Message::Close(Some(frame))).await {
+        tracing::error!("failed to close connection: {:?}", e);
+    }
 }
 
-fn is_local_ipv4(ip: IpAddr) -> bool {
-    if let IpAddr::V4(ipv4) = ip {
-        return ipv4.is_private();
+/// Saves the list every interval
+async fn save_snapshots(server_list: ServerList, path: PathBuf, interval: Duration) {
+    let mut interval = tokio::time::interval(interval);
+    // the first tick completes immediately, which would overwrite the snapshot just restored
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        save_snapshot(&server_list, &path).await;
     }
-    return false;
 }
 
-fn remove_server(mut server_list: ServerList, game_id: &Uuid) {
-    match server_list.remove(game_id) {
-        Some(entry) => {
+// other games are saved next to the default game, e.g. snapshot.race.json
+fn snapshot_path(path: &FilePath, game: &str) -> PathBuf {
+    if game == DEFAULT_GAME {
+        return path.to_path_buf();
+    }
+    let mut file_name = path.file_stem().unwrap_or_default().to_os_string();
+    file_name.push(format!(".{}", game));
+    if let Some(extension) = path.extension() {
+        file_name.push(".");
+        file_name.push(extension);
+    }
+    path.with_file_name(file_name)
+}
+
+async fn save_snapshot(server_list: &ServerList, path: &FilePath) {
+    let snapshot = server_list.snapshot();
+    match snapshot::save(path, &snapshot).await {
+        Ok(()) => tracing::debug!(
+            "saved {} game servers to {:?}",
+            snapshot.servers.len(),
+            path
+        ),
+        Err(e) => tracing::error!("failed to save snapshot to {:?}: {}", path, e),
+    }
+}
+
+/// Removes disconnected game servers once their grace period to resume has passed
+async fn expire_servers(game: Game, grace_period: Duration, reservations: ReservationsById) {
+    let mut server_list = game.server_list.clone();
+    let mut interval = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        interval.tick().await;
+        for entry in server_list.remove_expired(grace_period) {
+            reservations.lock().unwrap().remove(&entry.id());
             // remove players from metrics
-            IN_GAME_PLAYERS.set(IN_GAME_PLAYERS.get() - i64::from(entry.players));
-            tracing::info!("deleted game server: {:?}", entry)
+            IN_GAME_PLAYERS
+                .with_label_values(&[&game.name])
+                .sub(i64::from(entry.players));
+            tracing::info!("deleted game server: {:?}", entry);
         }
-        None => tracing::error!("failed to remove game server with id: {:?}", game_id),
-    }
-    // remove server from metrics
-    CONNECTED_GAME_SERVERS.dec();
-}
-
-fn parse_connect_message(txt: String, ip: IpAddr, server_ip: IpAddr) -> Result<GameServer, String> {
-    if let Ok(msg) = serde_json::from_str::<ConnectMessage>(&txt) {
-        match msg {
-            ConnectMessage::V1 { name, port } => {
-                tracing::debug!("new game connected with V1 name: {} port: {}", name, port);
-                // if this IP is local then it's on the same host so
-                // replace the it with the server's public IP
-                let mut official = false;
-                let ip = if is_local_ipv4(ip) {
-                    official = true;
-                    server_ip
-                } else {
-                    ip
-                };
+    }
+}
 
-                return Ok(GameServer::new(name, ip, false, port, official));
-            }
-            ConnectMessage::V2 { name, tls, port } => {
-                tracing::debug!(
-                    "new game connected with V2 name: {} tls: {} port: {}",
-                    name,
-                    tls,
-                    port
-                );
-                // if this IP is local then it's on the same host so
-                // replace the it with the server's public IP
-                let mut official = false;
-                let ip = if is_local_ipv4(ip) {
-                    official = true;
-                    server_ip
-                } else {
-                    ip
-                };
-                return Ok(GameServer::new(name, ip, tls, port, official));
-            }
+/// Parses the connect message and checks the game server is allowed to register, also returning
+/// the token to resume an existing entry with
+/// Counts the connecting game server towards its IP's limit, returning `None` if the IP already
+/// has `max` servers. A server resuming its entry from the same IP keeps the entry's slot
+fn take_ip_slot(txt: &str, ip: IpAddr, max: usize, game: &Game) -> Option<Arc<IpSlot>> {
+    let resumed = parse_connect_options(txt)
+        .ok()
+        .and_then(|options| options.resume_token)
+        .and_then(|resume_token| game.server_list.ip_slot(&resume_token))
+        .filter(|ip_slot| ip_slot.is_for(ip));
+    resumed.or_else(|| game.servers_per_ip.try_acquire(ip, max).map(Arc::new))
+}
+
+async fn register(
+    txt: String,
+    ip: IpAddr,
+    header_token: Option<String>,
+    game: &Game,
+    app_state: &AppState,
+) -> Result<(GameServer, Option<String>), ListError> {
+    // without a public ip local game servers are listed with their own ip
+    let server_ip = app_state.server_ip.unwrap_or(ip);
+    let options = parse_connect_options(&txt)?;
+    // the token can be sent in the Authorization header or alongside the server info
+    let token = header_token.or(options.token);
+    let server = parse_connect_message(txt, ip, server_ip, &app_state.address_ranges)?;
+    game.check_version(&server)?;
+    let checked = check_name(&app_state.names, &app_state.bans, ip, server.name())?;
+    if checked.flagged {
+        tracing::warn!("flagged game server name: {:?}", checked.name);
+    }
+    let server = server.with_name(checked.name).with_flagged(checked.flagged);
+    let server = match options.alternate_ip {
+        Some(alternate_ip) => {
+            let alternate_ip = check_alternate_ip(server.ip(), alternate_ip)
+                .map_err(|e| ListError::new(ErrorCode::InvalidConnectMessage, e))?;
+            server.with_alternate_ip(alternate_ip)
         }
+        None => server,
+    };
+    // servers listed under the public ip can also be reached on its other address family
+    let server = match app_state.server_ipv6 {
+        Some(ipv6) if app_state.address_ranges.should_rewrite(ip) => {
+            server.with_alternate_ip(IpAddr::V6(ipv6))
+        }
+        _ => server,
+    };
+    let trust = game.auth.check(token.as_deref())?;
+    let server = server.with_trust(trust);
+    let server = match options.hostname {
+        Some(hostname) => {
+            let ips = [ip, server.ip()];
+            let hostname = app_state.hostnames.verify(&hostname, &ips).await?;
+            server.with_hostname(hostname)
+        }
+        None => server,
+    };
+    Ok((server, options.resume_token))
+}
+
+/// Parses the options sent alongside the server info, rejecting them if any are malformed rather
+/// than leaving them all out
+fn parse_connect_options(txt: &str) -> Result<ConnectOptions, ListError> {
+    serde_json::from_str::<ConnectOptions>(txt).map_err(|e| {
+        ListError::new(
+            ErrorCode::InvalidConnectMessage,
+            format!("failed to parse connect options: {}", e),
+        )
+    })
+}
+
+fn parse_connect_message(
+    txt: String,
+    ip: IpAddr,
+    server_ip: IpAddr,
+    ranges: &AddressRanges,
+) -> Result<GameServer, ListError> {
+    // the payload isn't echoed back as it can hold the token or resume token, which would end up in
+    // logs and the close frame
+    let msg = serde_json::from_str::<ConnectMessage>(&txt).map_err(|e| {
+        ListError::new(
+            ErrorCode::InvalidConnectMessage,
+            format!("failed to parse ConnectMessage: {}", e),
+        )
+    })?;
+    tracing::debug!("new game connected with {:?}", msg);
+    // if this IP is local then it's on the same host so
+    // replace the it with the server's public IP
+    let official = ranges.is_official(ip);
+    let ip = if ranges.should_rewrite(ip) {
+        server_ip
+    } else {
+        ip
+    };
+    let server = match msg {
+        ConnectMessage::V1 { name, port } => GameServer::new(name, ip, false, port, official),
+        ConnectMessage::V2 { name, tls, port } => GameServer::new(name, ip, tls, port, official),
+        ConnectMessage::V3 {
+            name,
+            port,
+            tls,
+            max_players,
+            map,
+            game_mode,
+            game_version,
+            password_protected,
+            tags,
+            region,
+        } => GameServer::new(name, ip, tls, port, official).with_metadata(ServerMetadata {
+            max_players: Some(max_players),
+            map,
+            game_mode,
+            game_version,
+            password_protected,
+            tags,
+            region,
+        }),
+    };
+    Ok(server)
+}
+
+/// Checks the game server's name then its bans, so a name ban can't be dodged with a name that
+/// only matches once normalised
+fn check_name(
+    names: &NamePolicy,
+    bans: &BanList,
+    ip: IpAddr,
+    name: &str,
+) -> Result<CheckedName, ListError> {
+    let checked = names.check(name)?;
+    if let Some(ban) = bans.find(ip, &checked.name) {
+        tracing::warn!("rejected banned game server: {:?}", ban);
+        return Err(ListError::new(ErrorCode::Banned, ban.message()));
     }
-    Err(format!(
-        "failed to parse ConnectMessage from JSON data: {:#?}",
-        txt
-    ))
+    Ok(checked)
 }
 
-fn parse_game_message(server_list: &ServerList, server_id: &Uuid, msg: &str) {
+fn parse_game_message(
+    game: &Game,
+    server_id: &Uuid,
+    msg: &str,
+    reservations: &mut Reservations,
+) -> Result<(), ListError> {
+    let server_list = &game.server_list;
     if let Ok(json) = serde_json::from_str::<GameMessage>(msg) {
         match json {
             GameMessage::Status { players } => {
                 server_list.update(server_id, |game_server| {
                     // calculate player count difference to update metrics
                     let player_diff: i64 = i64::from(players) - i64::from(game_server.players);
-                    IN_GAME_PLAYERS.set(IN_GAME_PLAYERS.get() + player_diff);
+                    IN_GAME_PLAYERS
+                        .with_label_values(&[&game.name])
+                        .add(player_diff);
                     game_server.players = players;
                     tracing::info!("updated player count of server: {:?}", game_server);
                 });
             }
+            GameMessage::Reservation {
+                reservation_id,
+                status,
+                reason,
+            } => {
+                tracing::info!("reservation {} answered: {:?}", reservation_id, status);
+                if reservations.answer(reservation_id, status, reason)? {
+                    let reserved = reservations.held();
+                    server_list.update(server_id, |game_server| game_server.reserved = reserved);
+                }
+            }
+            GameMessage::Details { map, game_mode } => {
+                if map.is_none() && game_mode.is_none() {
+                    return Err(ListError::new(
+                        ErrorCode::InvalidGameMessage,
+                        "game details update must contain a map or game_mode",
+                    ));
+                }
+                server_list.update(server_id, |game_server| {
+                    if map.is_some() {
+                        game_server.metadata.map = map;
+                    }
+                    if game_mode.is_some() {
+                        game_server.metadata.game_mode = game_mode;
+                    }
+                    tracing::info!("updated details of server: {:?}", game_server);
+                });
+            }
         }
+        Ok(())
     } else {
-        tracing::error!("failed to parse GameMessage from JSON data: {:#?}", msg);
+        Err(ListError::new(
+            ErrorCode::InvalidGameMessage,
+            format!("failed to parse GameMessage from JSON data: {:#?}", msg),
+        ))
     }
 }
 
@@ -382,35 +1975,67 @@ mod tests {
     use super::*;
     use std::net::Ipv4Addr;
 
+    #[test]
+    fn reject_zero_intervals() {
+        let env = |name: &str, value: &str| [(String::from(name), String::from(value))];
+        let config = envy::from_iter::<_, Config>(env("HEARTBEAT_INTERVAL_SECS", "5")).unwrap();
+        assert_eq!(config.heartbeat_interval_secs, 5);
+        assert_eq!(config.probe_interval_secs, default_probe_interval_secs());
+        for name in [
+            "HEARTBEAT_INTERVAL_SECS",
+            "IDLE_TIMEOUT_SECS",
+            "PROBE_INTERVAL_SECS",
+            "FEDERATION_INTERVAL_SECS",
+            "SNAPSHOT_INTERVAL_SECS",
+        ] {
+            assert!(
+                envy::from_iter::<_, Config>(env(name, "0")).is_err(),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn reject_reservation_timeout_past_request_timeout() {
+        let env = |value: &str| [(String::from("RESERVATION_TIMEOUT_MS"), String::from(value))];
+        let config = envy::from_iter::<_, Config>(env("9999")).unwrap();
+        assert_eq!(config.reservation_timeout_ms, 9999);
+        assert!(envy::from_iter::<_, Config>(env("0")).is_err());
+        assert!(envy::from_iter::<_, Config>(env("10000")).is_err());
+    }
+
     #[test]
     fn parse_connect_message_v2() {
         let txt = "{\"name\":\"Test's Game\",\"port\":31400,\"tls\":true}".to_string();
-        let ip = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
-        let server_ip = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+        let ip = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7));
+        let server_ip = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 1));
         let expected_server = GameServer::new(
             String::from("Test's Game"),
-            IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+            IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7)),
             true,
             31400,
             false,
         );
-        let result: Result<GameServer, String> = parse_connect_message(txt, ip, server_ip);
+        let result: Result<GameServer, ListError> =
+            parse_connect_message(txt, ip, server_ip, &AddressRanges::default());
         assert_eq!(result, Ok(expected_server));
     }
 
     #[test]
     fn parse_connect_message_v2_reverse_order() {
         let txt = "{\"tls\":true, \"port\":31400, \"name\":\"Test's Game\"}".to_string();
-        let ip = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
-        let server_ip = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+        let ip = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7));
+        let server_ip = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 1));
         let expected_server = GameServer::new(
             String::from("Test's Game"),
-            IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+            IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7)),
             true,
             31400,
             false,
         );
-        let result: Result<GameServer, String> = parse_connect_message(txt, ip, server_ip);
+        let result: Result<GameServer, ListError> =
+            parse_connect_message(txt, ip, server_ip, &AddressRanges::default());
         assert_eq!(result, Ok(expected_server));
     }
 
@@ -426,39 +2051,42 @@ mod tests {
             65535,
             true,
         );
-        let result: Result<GameServer, String> = parse_connect_message(txt, ip, server_ip);
+        let result: Result<GameServer, ListError> =
+            parse_connect_message(txt, ip, server_ip, &AddressRanges::default());
         assert_eq!(result, Ok(expected_server));
     }
 
     #[test]
     fn parse_connect_message_v1() {
         let txt = "{\"name\":\"Test\",\"port\":12345}".to_string();
-        let ip = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
-        let server_ip = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+        let ip = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7));
+        let server_ip = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 1));
         let expected_server = GameServer::new(
             String::from("Test"),
-            IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+            IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7)),
             false,
             12345,
             false,
         );
-        let result: Result<GameServer, String> = parse_connect_message(txt, ip, server_ip);
+        let result: Result<GameServer, ListError> =
+            parse_connect_message(txt, ip, server_ip, &AddressRanges::default());
         assert_eq!(result, Ok(expected_server));
     }
 
     #[test]
     fn parse_connect_message_v1_reverse_order() {
         let txt = "{\"port\":12345, \"name\":\"Test\"}".to_string();
-        let ip = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
-        let server_ip = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+        let ip = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7));
+        let server_ip = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 1));
         let expected_server = GameServer::new(
             String::from("Test"),
-            IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+            IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7)),
             false,
             12345,
             false,
         );
-        let result: Result<GameServer, String> = parse_connect_message(txt, ip, server_ip);
+        let result: Result<GameServer, ListError> =
+            parse_connect_message(txt, ip, server_ip, &AddressRanges::default());
         assert_eq!(result, Ok(expected_server));
     }
 
@@ -474,7 +2102,8 @@ mod tests {
             12345,
             true,
         );
-        let result: Result<GameServer, String> = parse_connect_message(txt, ip, server_ip);
+        let result: Result<GameServer, ListError> =
+            parse_connect_message(txt, ip, server_ip, &AddressRanges::default());
         assert_eq!(result, Ok(expected_server));
     }
 
@@ -483,7 +2112,215 @@ mod tests {
         let txt = "{\"wasd\":\"Test\",\"port\":12345,\"asdoasdoaisd\":59912}".to_string();
         let ip = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
         let server_ip = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
-        let result: Result<GameServer, String> = parse_connect_message(txt, ip, server_ip);
+        let result: Result<GameServer, ListError> =
+            parse_connect_message(txt, ip, server_ip, &AddressRanges::default());
         assert!(result.is_err());
     }
+
+    #[test]
+    fn parse_connect_message_hides_secrets() {
+        let txt = "{\"name\":\"Test\",\"token\":\"secret\",\"resume_token\":\"resume\"}";
+        let ip = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+        let error =
+            parse_connect_message(txt.to_string(), ip, ip, &AddressRanges::default()).unwrap_err();
+        assert_eq!(error.code, ErrorCode::InvalidConnectMessage);
+        assert!(!error.message.contains("secret"));
+        assert!(!error.message.contains("resume"));
+    }
+
+    #[test]
+    fn parse_connect_options_malformed() {
+        let txt = "{\"name\":\"Test\",\"token\":\"secret\",\"alternate_ip\":\"2001:db8::1\"}";
+        let options = parse_connect_options(txt).unwrap();
+        assert_eq!(options.token.as_deref(), Some("secret"));
+        assert!(options.alternate_ip.is_some());
+        for txt in [
+            "{\"name\":\"Test\",\"token\":\"secret\",\"alternate_ip\":\"x\"}",
+            "{\"name\":\"Test\",\"token\":\"secret\",\"hostname\":5}",
+        ] {
+            let error = parse_connect_options(txt).unwrap_err();
+            assert_eq!(error.code, ErrorCode::InvalidConnectMessage);
+            assert!(!error.message.contains("secret"));
+        }
+    }
+
+    #[test]
+    fn parse_connect_message_v3() {
+        let txt = "{\"name\":\"Test\",\"port\":12345,\"tls\":false,\"max_players\":8,\"map\":\"Forest\",\"tags\":[\"casual\"]}".to_string();
+        let ip = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7));
+        let server_ip = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 1));
+        let expected_server = GameServer::new(
+            String::from("Test"),
+            IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7)),
+            false,
+            12345,
+            false,
+        )
+        .with_metadata(ServerMetadata {
+            max_players: Some(8),
+            map: Some(String::from("Forest")),
+            tags: vec![String::from("casual")],
+            ..Default::default()
+        });
+        let result: Result<GameServer, ListError> =
+            parse_connect_message(txt, ip, server_ip, &AddressRanges::default());
+        assert_eq!(result, Ok(expected_server));
+    }
+
+    #[test]
+    fn snapshot_path_per_game() {
+        let path = FilePath::new("/data/snapshot.json");
+        assert_eq!(snapshot_path(path, DEFAULT_GAME), path);
+        assert_eq!(
+            snapshot_path(path, "race"),
+            FilePath::new("/data/snapshot.race.json")
+        );
+        assert_eq!(
+            snapshot_path(FilePath::new("snapshot"), "race"),
+            FilePath::new("snapshot.race")
+        );
+    }
+
+    #[test]
+    fn parse_game_message_details() {
+        let server = GameServer::new(
+            String::from("Test"),
+            IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+            false,
+            12345,
+            false,
+        );
+        let mut server_list = ServerList::new();
+        let server_id = server_list.add(server);
+        let game = Game::new(
+            String::from(DEFAULT_GAME),
+            server_list.clone(),
+            &GameConfig::default(),
+            &GameConfig::default(),
+        );
+        let result = parse_game_message(
+            &game,
+            &server_id,
+            "{\"game_mode\":\"Race\"}",
+            &mut Reservations::default(),
+        );
+        assert_eq!(result, Ok(()));
+        let servers = server_list.get(&ServerQuery::default());
+        assert_eq!(servers[0].metadata.game_mode, Some(String::from("Race")));
+        assert_eq!(servers[0].metadata.map, None);
+
+        let result = parse_game_message(
+            &game,
+            &server_id,
+            "{\"unknown\":1}",
+            &mut Reservations::default(),
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn parse_game_message_reservation() {
+        let server = GameServer::new(
+            String::from("Test"),
+            IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+            false,
+            12345,
+            false,
+        );
+        let mut server_list = ServerList::new();
+        let server_id = server_list.add(server);
+        let game = Game::new(
+            String::from(DEFAULT_GAME),
+            server_list.clone(),
+            &GameConfig::default(),
+            &GameConfig::default(),
+        );
+        let mut reservations = Reservations::default();
+        let reservation_id = Uuid::new_v4();
+        let (answer, mut answered) = oneshot::channel();
+        let expires = Instant::now() + Duration::from_secs(30);
+        reservations.request(reservation_id, expires, answer);
+
+        let accepted = format!(
+            "{{\"reservation_id\":\"{}\",\"status\":\"accepted\"}}",
+            reservation_id
+        );
+        let result = parse_game_message(&game, &server_id, &accepted, &mut reservations);
+        assert_eq!(result, Ok(()));
+        assert_eq!(answered.try_recv(), Ok(Ok(())));
+        assert_eq!(server_list.server(&server_id).unwrap().reserved, 1);
+
+        let claimed = accepted.replace("accepted", "claimed");
+        let result = parse_game_message(&game, &server_id, &claimed, &mut reservations);
+        assert_eq!(result, Ok(()));
+        assert_eq!(server_list.server(&server_id).unwrap().reserved, 0);
+        let result = parse_game_message(&game, &server_id, &claimed, &mut reservations);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn check_name_banned() {
+        let ip = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+        let names = NamePolicy::new(1, 64, Vec::new());
+        let bans = BanList::new();
+        let name = BanTarget::Name(String::from("cheater's game"));
+        bans.add(name, None, Some(String::from("cheating")));
+        let result = check_name(&names, &bans, ip, "Cheater's Game").unwrap_err();
+        assert_eq!(result.code, ErrorCode::Banned);
+        assert_eq!(result.message, "banned from the list: cheating");
+
+        // names are banned as they'd be listed, after normalising
+        bans.add(BanTarget::Name(String::from("Spam")), None, None);
+        for name in [" Spam", "Spam  ", "Ｓｐａｍ"] {
+            let result = check_name(&names, &bans, ip, name).unwrap_err();
+            assert_eq!(result.code, ErrorCode::Banned, "{:?}", name);
+        }
+        assert!(check_name(&names, &bans, ip, "Spam Race").is_ok());
+    }
+
+    #[test]
+    fn parse_connect_message_custom_ranges() {
+        let txt = "{\"name\":\"Test\",\"port\":12345}".to_string();
+        let ip = IpAddr::V4(Ipv4Addr::new(10, 42, 0, 7));
+        let server_ip = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 5));
+        // official pods keep their own address while anything else private is rewritten
+        let ranges = AddressRanges::new(
+            vec!["10.42.0.0/16".parse().unwrap()],
+            vec!["192.168.0.0/16".parse().unwrap()],
+        );
+        let expected_server = GameServer::new(String::from("Test"), ip, false, 12345, true);
+        let result = parse_connect_message(txt.clone(), ip, server_ip, &ranges);
+        assert_eq!(result, Ok(expected_server));
+
+        let ip = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 2));
+        let expected_server = GameServer::new(String::from("Test"), server_ip, false, 12345, false);
+        let result = parse_connect_message(txt, ip, server_ip, &ranges);
+        assert_eq!(result, Ok(expected_server));
+    }
+
+    #[test]
+    fn parse_connect_message_loopback() {
+        let txt = "{\"name\":\"Test\",\"port\":12345}".to_string();
+        let server_ip = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 5));
+        let expected_server = GameServer::new(String::from("Test"), server_ip, false, 12345, true);
+        for ip in [Ipv4Addr::new(127, 0, 0, 1), Ipv4Addr::new(127, 1, 2, 3)] {
+            let result = parse_connect_message(
+                txt.clone(),
+                IpAddr::V4(ip),
+                server_ip,
+                &AddressRanges::default(),
+            );
+            assert_eq!(result, Ok(expected_server.clone()));
+        }
+    }
+
+    #[test]
+    fn parse_connect_message_ipv6_loopback() {
+        let txt = "{\"name\":\"Test\",\"port\":12345}".to_string();
+        let ip = IpAddr::V6(Ipv6Addr::LOCALHOST);
+        let server_ip = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 5));
+        let expected_server = GameServer::new(String::from("Test"), server_ip, false, 12345, true);
+        let result = parse_connect_message(txt, ip, server_ip, &AddressRanges::default());
+        assert_eq!(result, Ok(expected_server));
+    }
 }
@@ -0,0 +1,255 @@
+use crate::{ErrorCode, ListError};
+use serde::Deserialize;
+use std::{collections::HashSet, ops::Range, sync::Arc};
+use unicode_normalization::UnicodeNormalization;
+
+// invisible characters which can hide text or reverse how the rest of the list is displayed
+const INVISIBLE: [(char, char); 5] = [
+    ('\u{200B}', '\u{200F}'),
+    ('\u{202A}', '\u{202E}'),
+    ('\u{2060}', '\u{2064}'),
+    ('\u{2066}', '\u{2069}'),
+    ('\u{FEFF}', '\u{FEFF}'),
+];
+
+/// What happens to names the moderation filter matches
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ModerationAction {
+    /// The game server isn't allowed to register
+    #[default]
+    Reject,
+    /// The matched text is replaced with `*`
+    Mask,
+    /// The name is listed as is but the game server is flagged for admins to review
+    Flag,
+}
+
+/// Finds text which isn't allowed in names
+pub trait NameFilter: Send + Sync {
+    /// Returns the byte ranges of the name which matched
+    fn find(&self, name: &str) -> Vec<Range<usize>>;
+}
+
+/// Matches whole words from a list, ignoring case
+#[derive(Debug, Clone, Default)]
+pub struct WordList {
+    words: HashSet<String>,
+}
+
+impl WordList {
+    pub fn new<I: IntoIterator<Item = S>, S: AsRef<str>>(words: I) -> WordList {
+        let words = words
+            .into_iter()
+            .map(|word| word.as_ref().trim().to_lowercase())
+            .filter(|word| !word.is_empty())
+            .collect();
+        WordList { words }
+    }
+    /// Parses one word per line, skipping empty lines and `#` comments
+    pub fn parse(text: &str) -> WordList {
+        WordList::new(text.lines().filter(|line| !line.trim().starts_with('#')))
+    }
+}
+
+impl NameFilter for WordList {
+    fn find(&self, name: &str) -> Vec<Range<usize>> {
+        let mut matches = Vec::new();
+        let mut start = None;
+        // the end of the name also ends the last word
+        let chars = name.char_indices().chain([(name.len(), ' ')]);
+        for (index, c) in chars {
+            match (start, c.is_alphanumeric()) {
+                (None, true) => start = Some(index),
+                (Some(word_start), false) => {
+                    if self.words.contains(&name[word_start..index].to_lowercase()) {
+                        matches.push(word_start..index);
+                    }
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        matches
+    }
+}
+
+/// The name a game server is listed under once checked
+#[derive(Debug, Clone, PartialEq)]
+pub struct CheckedName {
+    pub name: String,
+    /// Matched the moderation filter with [`ModerationAction::Flag`]
+    pub flagged: bool,
+}
+
+/// Normalises game server names and checks they're allowed in the list
+#[derive(Clone)]
+pub struct NamePolicy {
+    min_length: usize,
+    max_length: usize,
+    disallowed_chars: Vec<char>,
+    filter: Option<Arc<dyn NameFilter>>,
+    action: ModerationAction,
+}
+
+impl NamePolicy {
+    /// Allows names between the lengths in characters without any of the disallowed characters
+    pub fn new(min_length: usize, max_length: usize, disallowed_chars: Vec<char>) -> NamePolicy {
+        NamePolicy {
+            min_length,
+            max_length,
+            disallowed_chars,
+            filter: None,
+            action: ModerationAction::default(),
+        }
+    }
+    /// Moderates names with the filter, taking the action on any it matches
+    pub fn with_filter(mut self, filter: Arc<dyn NameFilter>, action: ModerationAction) -> Self {
+        self.filter = Some(filter);
+        self.action = action;
+        self
+    }
+    /// Normalises the name then checks it, returning the name to list the game server under
+    pub fn check(&self, name: &str) -> Result<CheckedName, ListError> {
+        let name = normalize(name);
+        let invalid = |message: String| Err(ListError::new(ErrorCode::InvalidName, message));
+        let length = name.chars().count();
+        if length == 0 {
+            return invalid(String::from("name must not be empty"));
+        }
+        if length < self.min_length {
+            return invalid(format!(
+                "name must be at least {} characters",
+                self.min_length
+            ));
+        }
+        if length > self.max_length {
+            return invalid(format!(
+                "name must be at most {} characters",
+                self.max_length
+            ));
+        }
+        if let Some(c) = name
+            .chars()
+            .find(|c| c.is_control() || is_invisible(*c) || self.disallowed_chars.contains(c))
+        {
+            return invalid(format!("name must not contain {:?}", c));
+        }
+        let matches = match &self.filter {
+            Some(filter) => filter.find(&name),
+            None => Vec::new(),
+        };
+        if matches.is_empty() {
+            return Ok(CheckedName {
+                name,
+                flagged: false,
+            });
+        }
+        match self.action {
+            ModerationAction::Reject => invalid(String::from("name contains blocked words")),
+            ModerationAction::Mask => Ok(CheckedName {
+                name: mask(&name, &matches),
+                flagged: false,
+            }),
+            ModerationAction::Flag => Ok(CheckedName {
+                name,
+                flagged: true,
+            }),
+        }
+    }
+}
+
+/// Composes characters with NFKC, so lookalikes such as full width letters become the usual ones,
+/// then trims the name and collapses runs of whitespace into single spaces
+pub fn normalize(name: &str) -> String {
+    let name = name.nfkc().collect::<String>();
+    name.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn is_invisible(c: char) -> bool {
+    INVISIBLE
+        .iter()
+        .any(|(start, end)| (*start..=*end).contains(&c))
+}
+
+fn mask(name: &str, ranges: &[Range<usize>]) -> String {
+    name.char_indices()
+        .map(|(index, c)| {
+            if ranges.iter().any(|range| range.contains(&index)) {
+                '*'
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> NamePolicy {
+        NamePolicy::new(1, 16, vec!['<', '>'])
+    }
+
+    #[test]
+    fn normalize_names() {
+        let checked = policy().check("  Ｆｕｌｌ \t width\n").unwrap();
+        assert_eq!(checked.name, "Full width");
+    }
+
+    #[test]
+    fn reject_invalid_names() {
+        let policy = policy();
+        for name in [
+            "",
+            "   ",
+            "This name is far too long",
+            "<script>",
+            "Bell\u{7}",
+            "Hidden\u{202E}txet",
+        ] {
+            let error = policy.check(name).unwrap_err();
+            assert_eq!(error.code, ErrorCode::InvalidName, "{:?}", name);
+        }
+        assert!(policy.check("Racing 24/7 🏁").is_ok());
+    }
+
+    #[test]
+    fn find_whole_words() {
+        let words = WordList::parse("# blocked words\nDarn\n\nheck\n");
+        assert_eq!(words.find("darn it, DARN"), vec![0..4, 9..13]);
+        assert!(words.find("Darnell's server").is_empty());
+    }
+
+    #[test]
+    fn moderation_actions() {
+        let words: Arc<dyn NameFilter> = Arc::new(WordList::new(["darn"]));
+        let with_action = |action| policy().with_filter(words.clone(), action);
+
+        let error = with_action(ModerationAction::Reject)
+            .check("Darn Race")
+            .unwrap_err();
+        assert_eq!(error.code, ErrorCode::InvalidName);
+        assert_eq!(
+            with_action(ModerationAction::Mask).check("Darn Race"),
+            Ok(CheckedName {
+                name: String::from("**** Race"),
+                flagged: false,
+            })
+        );
+        assert_eq!(
+            with_action(ModerationAction::Flag).check("Darn Race"),
+            Ok(CheckedName {
+                name: String::from("Darn Race"),
+                flagged: true,
+            })
+        );
+        assert!(
+            !with_action(ModerationAction::Flag)
+                .check("Fine Race")
+                .unwrap()
+                .flagged
+        );
+    }
+}
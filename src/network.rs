@@ -0,0 +1,206 @@
+use ipnet::IpNet;
+use serde::{Deserialize, Deserializer};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// Decides which game servers are official and which are listed under the server list's public IP,
+/// based on the IP they connect from
+#[derive(Debug, Clone)]
+pub struct AddressRanges {
+    official: Vec<IpNet>,
+    rewrite: Vec<IpNet>,
+}
+
+impl AddressRanges {
+    pub fn new(official: Vec<IpNet>, rewrite: Vec<IpNet>) -> AddressRanges {
+        AddressRanges { official, rewrite }
+    }
+    pub fn is_official(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        self.official.iter().any(|range| range.contains(&ip))
+    }
+    /// Whether the IP is on the same network as the server list so should be replaced by its
+    /// public IP
+    pub fn should_rewrite(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        self.rewrite.iter().any(|range| range.contains(&ip))
+    }
+}
+
+impl Default for AddressRanges {
+    fn default() -> Self {
+        AddressRanges::new(private_ranges(), private_ranges())
+    }
+}
+
+/// The RFC1918 private IPv4 ranges and loopback, along with IPv6 loopback, unique local and
+/// link-local addresses
+pub fn private_ranges() -> Vec<IpNet> {
+    [
+        "127.0.0.0/8",
+        "10.0.0.0/8",
+        "172.16.0.0/12",
+        "192.168.0.0/16",
+        "::1/128",
+        "fc00::/7",
+        "fe80::/10",
+    ]
+    .iter()
+    .map(|range| range.parse().expect("range is valid"))
+    .collect()
+}
+
+/// Whether the address can be reached over the internet, rather than being private, loopback,
+/// link-local, multicast or reserved for documentation
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip.to_canonical() {
+        IpAddr::V4(ipv4) => is_public_ipv4(ipv4),
+        IpAddr::V6(ipv6) => is_public_ipv6(ipv6),
+    }
+}
+
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    // 100.64.0.0/10 is shared by carrier-grade NATs
+    let shared = ip.octets()[0] == 100 && (ip.octets()[1] & 0xc0) == 64;
+    !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || ip.is_documentation()
+        || shared)
+}
+
+fn is_public_ipv6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    let unique_local = (first & 0xfe00) == 0xfc00;
+    let link_local = (first & 0xffc0) == 0xfe80;
+    let documentation = first == 0x2001 && ip.segments()[1] == 0x0db8;
+    !(ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        || unique_local
+        || link_local
+        || documentation)
+}
+
+/// Checks an address a game server advertises alongside the one it's listed under, it must be
+/// public and of the other address family
+pub fn check_alternate_ip(listed: IpAddr, alternate: IpAddr) -> Result<IpAddr, String> {
+    let alternate = alternate.to_canonical();
+    if alternate.is_ipv4() == listed.to_canonical().is_ipv4() {
+        let family = if alternate.is_ipv4() { "IPv6" } else { "IPv4" };
+        return Err(format!("alternate_ip must be an {} address", family));
+    }
+    if !is_public(alternate) {
+        return Err(format!(
+            "alternate_ip {} is not a public address",
+            alternate
+        ));
+    }
+    Ok(alternate)
+}
+
+/// Deserializes a list of CIDR ranges, skipping empty entries so an empty list can be configured
+pub fn deserialize_ranges<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<IpNet>, D::Error> {
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|range| range.trim())
+        .filter(|range| !range.is_empty())
+        .map(|range| range.parse().map_err(serde::de::Error::custom))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn default_ranges_are_private() {
+        let ranges = AddressRanges::default();
+        assert!(ranges.is_official(IpAddr::V4(Ipv4Addr::new(172, 16, 0, 22))));
+        assert!(ranges.should_rewrite(IpAddr::V4(Ipv4Addr::new(10, 1, 2, 3))));
+        assert!(!ranges.is_official(IpAddr::V4(Ipv4Addr::new(8, 8, 8, 8))));
+        // loopback is the same host whichever address family it's reached over
+        assert!(ranges.is_official(IpAddr::V4(Ipv4Addr::LOCALHOST)));
+        assert!(ranges.should_rewrite(IpAddr::V4(Ipv4Addr::LOCALHOST)));
+    }
+
+    #[test]
+    fn default_ranges_ipv6() {
+        let ranges = AddressRanges::default();
+        assert!(ranges.is_official("::1".parse().unwrap()));
+        assert!(ranges.is_official("fd12:3456::1".parse().unwrap()));
+        assert!(ranges.should_rewrite("fe80::1".parse().unwrap()));
+        assert!(!ranges.is_official("2001:db8::1".parse().unwrap()));
+        // IPv4 clients of a dual-stack socket show up as IPv4-mapped IPv6 addresses
+        assert!(ranges.is_official("::ffff:192.168.0.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn public_addresses() {
+        assert!(is_public("8.8.8.8".parse().unwrap()));
+        assert!(is_public("2a00:1450::1".parse().unwrap()));
+        assert!(is_public("::ffff:8.8.8.8".parse().unwrap()));
+        for ip in [
+            "10.0.0.1",
+            "127.0.0.1",
+            "100.64.0.1",
+            "169.254.0.1",
+            "0.0.0.0",
+            "203.0.113.7",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "ff02::1",
+            "2001:db8::1",
+            "::ffff:192.168.0.1",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn alternate_ip_of_other_family() {
+        let listed: IpAddr = "8.8.8.8".parse().unwrap();
+        let ipv6: IpAddr = "2a00:1450::1".parse().unwrap();
+        assert_eq!(check_alternate_ip(listed, ipv6), Ok(ipv6));
+        assert_eq!(
+            check_alternate_ip(ipv6, "::ffff:8.8.8.8".parse().unwrap()),
+            Ok(listed)
+        );
+        assert!(check_alternate_ip(listed, "1.1.1.1".parse().unwrap()).is_err());
+        assert!(check_alternate_ip(listed, "fd00::1".parse().unwrap()).is_err());
+    }
+
+    #[test]
+    fn custom_ranges() {
+        let official = vec![
+            "10.42.0.0/16".parse().unwrap(),
+            "2001:db8::/32".parse().unwrap(),
+        ];
+        let ranges = AddressRanges::new(official, Vec::new());
+        assert!(ranges.is_official("10.42.1.1".parse().unwrap()));
+        assert!(ranges.is_official("2001:db8::1".parse().unwrap()));
+        assert!(!ranges.is_official("10.43.1.1".parse().unwrap()));
+        assert!(!ranges.should_rewrite("10.42.1.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn deserialize_range_list() {
+        #[derive(Deserialize)]
+        struct Config {
+            #[serde(deserialize_with = "deserialize_ranges")]
+            ranges: Vec<IpNet>,
+        }
+        let config: Config =
+            envy::from_iter([(String::from("RANGES"), String::from("10.0.0.0/8,fd00::/8"))])
+                .unwrap();
+        assert_eq!(config.ranges.len(), 2);
+        let config: Config = envy::from_iter([(String::from("RANGES"), String::new())]).unwrap();
+        assert!(config.ranges.is_empty());
+    }
+}
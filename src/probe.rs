@@ -0,0 +1,79 @@
+use std::{net::SocketAddr, time::Duration};
+use tokio::{net::TcpStream, sync::mpsc};
+
+/// How game servers are checked to be reachable before being listed
+#[derive(Debug, Clone, Copy)]
+pub struct ProbeConfig {
+    /// How long to wait for a connection before giving up
+    pub timeout: Duration,
+    /// How often to check the game server is still reachable
+    pub interval: Duration,
+}
+
+/// Returns whether a TCP connection can be made to the address
+pub async fn probe(addr: SocketAddr, timeout: Duration) -> bool {
+    match tokio::time::timeout(timeout, TcpStream::connect(addr)).await {
+        Ok(Ok(_)) => true,
+        Ok(Err(e)) => {
+            tracing::debug!("failed to probe {}: {}", addr, e);
+            false
+        }
+        Err(_) => {
+            tracing::debug!("timed out probing {}", addr);
+            false
+        }
+    }
+}
+
+/// Probes the address every interval, sending the result whenever it changes
+///
+/// Stops once the receiver is dropped.
+pub async fn probe_loop(addr: SocketAddr, config: ProbeConfig, results: mpsc::Sender<bool>) {
+    let mut interval = tokio::time::interval(config.interval);
+    let mut last_result = None;
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = results.closed() => break,
+        }
+        let reachable = probe(addr, config.timeout).await;
+        if last_result != Some(reachable) {
+            if results.send(reachable).await.is_err() {
+                break;
+            }
+            last_result = Some(reachable);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn probe_open_and_closed_ports() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        assert!(probe(addr, Duration::from_secs(1)).await);
+        drop(listener);
+        assert!(!probe(addr, Duration::from_secs(1)).await);
+    }
+
+    #[tokio::test]
+    async fn probe_loop_reports_changes() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let config = ProbeConfig {
+            timeout: Duration::from_secs(1),
+            interval: Duration::from_millis(10),
+        };
+        let (sender, mut results) = mpsc::channel(1);
+        let task = tokio::spawn(probe_loop(addr, config, sender));
+        assert_eq!(results.recv().await, Some(true));
+        drop(listener);
+        assert_eq!(results.recv().await, Some(false));
+        drop(results);
+        task.await.unwrap();
+    }
+}
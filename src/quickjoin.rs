@@ -0,0 +1,205 @@
+use crate::{version::VersionRule, GameServer, ServerStatus};
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Ordering,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+};
+use uuid::Uuid;
+
+/// What the client is looking for, every criterion is optional
+#[derive(Debug, Deserialize, Default)]
+pub struct QuickJoinQuery {
+    /// Only servers this version of the game can join
+    pub client_version: Option<String>,
+    /// Game mode the server must be running, ignoring case
+    pub game_mode: Option<String>,
+    /// Region the server must be in, ignoring case
+    pub region: Option<String>,
+}
+
+/// How servers are ranked, servers with the highest score are picked first
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScoringPolicy {
+    /// Added for how full the server is, from 0 when empty to the whole weight when one slot is
+    /// left, reserved slots count as taken
+    pub fill_weight: f64,
+    /// Added for official servers
+    pub official_weight: f64,
+}
+
+impl Default for ScoringPolicy {
+    fn default() -> Self {
+        ScoringPolicy {
+            fill_weight: 1.0,
+            official_weight: 0.5,
+        }
+    }
+}
+
+impl ScoringPolicy {
+    pub fn score(&self, server: &GameServer) -> f64 {
+        let fill = match server.metadata.max_players {
+            Some(max_players) if max_players > 1 => {
+                f64::from(server.players.saturating_add(server.reserved))
+                    / f64::from(max_players - 1)
+            }
+            _ => 0.0,
+        };
+        let official = if server.official() { 1.0 } else { 0.0 };
+        self.fill_weight * fill.min(1.0) + self.official_weight * official
+    }
+}
+
+/// Everything a client needs to connect to the server picked for it
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct ConnectionDetails {
+    pub id: Uuid,
+    pub name: String,
+    pub ip: IpAddr,
+    pub ipv4: Option<Ipv4Addr>,
+    pub ipv6: Option<Ipv6Addr>,
+    pub hostname: Option<String>,
+    pub port: u16,
+    pub tls: bool,
+}
+
+impl From<&GameServer> for ConnectionDetails {
+    fn from(server: &GameServer) -> Self {
+        ConnectionDetails {
+            id: server.id(),
+            name: server.name().to_string(),
+            ip: server.ip(),
+            ipv4: server.ipv4(),
+            ipv6: server.ipv6(),
+            hostname: server.hostname().map(String::from),
+            port: server.port(),
+            tls: server.tls,
+        }
+    }
+}
+
+/// Returns whether a client can join the server right now and it matches what the client wants
+fn joinable(server: &GameServer, query: &QuickJoinQuery, rule: VersionRule) -> bool {
+    let matches = |wanted: &Option<String>, actual: &Option<String>| match wanted {
+        Some(wanted) => actual
+            .as_ref()
+            .is_some_and(|actual| actual.eq_ignore_ascii_case(wanted)),
+        None => true,
+    };
+    let metadata = &server.metadata;
+    server.is_visible()
+        && server.status() == ServerStatus::Online
+        && !metadata.password_protected
+        && !server.is_full()
+        && matches(&query.game_mode, &metadata.game_mode)
+        && matches(&query.region, &metadata.region)
+        && query.client_version.as_ref().is_none_or(|client_version| {
+            rule.compatible(client_version, metadata.game_version.as_deref())
+        })
+}
+
+/// Picks the joinable server with the highest score, preferring fuller then older servers on ties
+pub fn best(
+    servers: Vec<GameServer>,
+    query: &QuickJoinQuery,
+    policy: &ScoringPolicy,
+    rule: VersionRule,
+) -> Option<GameServer> {
+    servers
+        .into_iter()
+        .filter(|server| joinable(server, query, rule))
+        .max_by(|a, b| {
+            policy
+                .score(a)
+                .partial_cmp(&policy.score(b))
+                .unwrap_or(Ordering::Equal)
+                .then_with(|| a.players.cmp(&b.players))
+                .then_with(|| b.id().cmp(&a.id()))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_server, ServerMetadata};
+
+    fn race_server(name: &str, players: u32, official: bool) -> GameServer {
+        test_server(name, players, official).with_metadata(ServerMetadata {
+            max_players: Some(8),
+            game_mode: Some(String::from("Race")),
+            region: Some(String::from("eu")),
+            ..Default::default()
+        })
+    }
+
+    fn pick(servers: Vec<GameServer>, query: &QuickJoinQuery, policy: &ScoringPolicy) -> String {
+        best(servers, query, policy, VersionRule::Exact)
+            .map(|server| server.name)
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn fill_nearly_full_servers_first() {
+        let servers = vec![
+            race_server("Empty", 0, false),
+            race_server("Nearly Full", 7, false),
+            race_server("Full", 8, false),
+            race_server("Half", 4, false),
+            GameServer {
+                reserved: 2,
+                ..test_server("Reserved", 6, false)
+            },
+        ];
+        let policy = ScoringPolicy::default();
+        assert_eq!(
+            pick(servers, &QuickJoinQuery::default(), &policy),
+            "Nearly Full"
+        );
+    }
+
+    #[test]
+    fn prefer_official_servers() {
+        let servers = vec![
+            race_server("Community", 4, false),
+            race_server("Official", 2, true),
+        ];
+        let policy = ScoringPolicy::default();
+        let query = QuickJoinQuery::default();
+        assert_eq!(pick(servers.clone(), &query, &policy), "Official");
+        let policy = ScoringPolicy {
+            official_weight: 0.0,
+            ..policy
+        };
+        assert_eq!(pick(servers, &query, &policy), "Community");
+    }
+
+    #[test]
+    fn match_criteria() {
+        let mut locked = race_server("Locked", 7, false);
+        locked.metadata.password_protected = true;
+        let mut us = race_server("US", 6, false);
+        us.metadata.region = Some(String::from("us"));
+        let mut old = race_server("Old", 5, false);
+        old.metadata.game_version = Some(String::from("1.0.0"));
+        let mut current = race_server("Current", 1, false);
+        current.metadata.game_version = Some(String::from("1.1.0"));
+        let servers = vec![locked, us, old, current];
+        let policy = ScoringPolicy::default();
+
+        let query = QuickJoinQuery {
+            region: Some(String::from("EU")),
+            ..Default::default()
+        };
+        assert_eq!(pick(servers.clone(), &query, &policy), "Old");
+        let query = QuickJoinQuery {
+            client_version: Some(String::from("1.1.0")),
+            ..Default::default()
+        };
+        assert_eq!(pick(servers.clone(), &query, &policy), "Current");
+        let query = QuickJoinQuery {
+            game_mode: Some(String::from("Deathmatch")),
+            ..Default::default()
+        };
+        assert_eq!(pick(servers, &query, &policy), "");
+    }
+}
@@ -0,0 +1,287 @@
+use crate::{unix_timestamp, ErrorCode, ListError, ReservationStatus};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::{
+    collections::HashMap,
+    fmt,
+    time::{Duration, Instant},
+};
+use tokio::sync::oneshot;
+use uuid::Uuid;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// What a join ticket vouches for, signed so game servers can trust it came from the list
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TicketClaims {
+    pub reservation_id: Uuid,
+    pub server_id: Uuid,
+    /// Unix timestamp in seconds the ticket stops being valid at
+    pub expires_at: u64,
+}
+
+impl TicketClaims {
+    /// Claims for a new reservation on the server, valid for the duration
+    pub fn new(server_id: Uuid, valid_for: Duration) -> TicketClaims {
+        TicketClaims {
+            reservation_id: Uuid::new_v4(),
+            server_id,
+            expires_at: unix_timestamp().saturating_add(valid_for.as_secs()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TicketError {
+    Malformed,
+    InvalidSignature,
+    Expired,
+}
+
+impl fmt::Display for TicketError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            TicketError::Malformed => "ticket is malformed",
+            TicketError::InvalidSignature => "ticket signature is invalid",
+            TicketError::Expired => "ticket has expired",
+        };
+        f.write_str(message)
+    }
+}
+
+impl std::error::Error for TicketError {}
+
+/// Signs join tickets with HMAC-SHA256, game servers verify them with the same secret
+///
+/// Tickets are the claims as base64url JSON, a `.`, then the base64url signature of the first part.
+#[derive(Clone)]
+pub struct TicketSigner {
+    secret: Vec<u8>,
+}
+
+impl TicketSigner {
+    pub fn new(secret: impl Into<Vec<u8>>) -> TicketSigner {
+        TicketSigner {
+            secret: secret.into(),
+        }
+    }
+    pub fn sign(&self, claims: &TicketClaims) -> String {
+        let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(claims).unwrap());
+        let signature = URL_SAFE_NO_PAD.encode(self.mac(&payload).finalize().into_bytes());
+        format!("{}.{}", payload, signature)
+    }
+    /// Checks the ticket was signed with this secret and hasn't expired
+    pub fn verify(&self, ticket: &str) -> Result<TicketClaims, TicketError> {
+        let (payload, signature) = ticket.split_once('.').ok_or(TicketError::Malformed)?;
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature)
+            .map_err(|_| TicketError::Malformed)?;
+        self.mac(payload)
+            .verify_slice(&signature)
+            .map_err(|_| TicketError::InvalidSignature)?;
+        let claims: TicketClaims = URL_SAFE_NO_PAD
+            .decode(payload)
+            .ok()
+            .and_then(|json| serde_json::from_slice(&json).ok())
+            .ok_or(TicketError::Malformed)?;
+        if claims.expires_at <= unix_timestamp() {
+            return Err(TicketError::Expired);
+        }
+        Ok(claims)
+    }
+    fn mac(&self, payload: &str) -> HmacSha256 {
+        let mut mac =
+            HmacSha256::new_from_slice(&self.secret).expect("HMAC takes keys of any size");
+        mac.update(payload.as_bytes());
+        mac
+    }
+}
+
+/// The game server's answer, an error holds the reason it rejected the reservation
+pub type Answer = Result<(), String>;
+
+struct Pending {
+    expires: Instant,
+    answer: oneshot::Sender<Answer>,
+}
+
+/// Reservations a game server was asked for or is holding slots for, kept by its connection
+#[derive(Default)]
+pub struct Reservations {
+    pending: HashMap<Uuid, Pending>,
+    // when each held slot is freed up again
+    held: HashMap<Uuid, Instant>,
+}
+
+impl Reservations {
+    /// Waits for the game server to answer the reservation, which is held until `expires` if
+    /// accepted
+    pub fn request(
+        &mut self,
+        reservation_id: Uuid,
+        expires: Instant,
+        answer: oneshot::Sender<Answer>,
+    ) {
+        // clients which stopped waiting won't be given a ticket
+        self.pending
+            .retain(|_, pending| !pending.answer.is_closed());
+        self.pending
+            .insert(reservation_id, Pending { expires, answer });
+    }
+    /// Passes the game server's answer on to the client, returning whether the held slots changed
+    pub fn answer(
+        &mut self,
+        reservation_id: Uuid,
+        status: ReservationStatus,
+        reason: Option<String>,
+    ) -> Result<bool, ListError> {
+        let unknown = || {
+            ListError::new(
+                ErrorCode::InvalidGameMessage,
+                format!("unknown reservation {}", reservation_id),
+            )
+        };
+        if status == ReservationStatus::Claimed {
+            return self
+                .held
+                .remove(&reservation_id)
+                .map(|_| true)
+                .ok_or_else(unknown);
+        }
+        let pending = self.pending.remove(&reservation_id).ok_or_else(unknown)?;
+        if status == ReservationStatus::Rejected {
+            let reason = reason.unwrap_or_else(|| String::from("rejected by the game server"));
+            let _ = pending.answer.send(Err(reason));
+            return Ok(false);
+        }
+        // the slot isn't held if the client gave up waiting, as it never got a ticket
+        if pending.answer.send(Ok(())).is_err() {
+            return Ok(false);
+        }
+        self.held.insert(reservation_id, pending.expires);
+        Ok(true)
+    }
+    /// Frees up slots whose tickets have expired, returning whether there were any
+    pub fn remove_expired(&mut self, now: Instant) -> bool {
+        let held = self.held.len();
+        self.held.retain(|_, expires| *expires > now);
+        self.held.len() != held
+    }
+    /// Drops the requests the game server hasn't answered, as it's no longer connected to answer
+    pub fn cancel_pending(&mut self) {
+        self.pending.clear();
+    }
+    /// When the next held slot is freed up
+    pub fn next_expiry(&self) -> Option<Instant> {
+        self.held.values().min().copied()
+    }
+    pub fn held(&self) -> u32 {
+        self.held.len().try_into().unwrap_or(u32::MAX)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn claims(expires_at: u64) -> TicketClaims {
+        TicketClaims {
+            reservation_id: Uuid::new_v4(),
+            server_id: Uuid::now_v7(),
+            expires_at,
+        }
+    }
+
+    #[test]
+    fn sign_and_verify_tickets() {
+        let signer = TicketSigner::new("secret");
+        let valid = TicketClaims::new(Uuid::now_v7(), Duration::from_secs(30));
+        let ticket = signer.sign(&valid);
+        assert_eq!(signer.verify(&ticket), Ok(valid));
+
+        let other = TicketSigner::new("other secret");
+        assert_eq!(other.verify(&ticket), Err(TicketError::InvalidSignature));
+        let (_, signature) = ticket.split_once('.').unwrap();
+        let forged = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&claims(u64::MAX)).unwrap());
+        assert_eq!(
+            signer.verify(&format!("{}.{}", forged, signature)),
+            Err(TicketError::InvalidSignature)
+        );
+        assert_eq!(signer.verify("not a ticket"), Err(TicketError::Malformed));
+
+        let expired = signer.sign(&claims(unix_timestamp() - 1));
+        assert_eq!(signer.verify(&expired), Err(TicketError::Expired));
+    }
+
+    #[test]
+    fn hold_accepted_slots() {
+        let mut reservations = Reservations::default();
+        let now = Instant::now();
+        let expires = now + Duration::from_secs(30);
+
+        let (sender, mut receiver) = oneshot::channel();
+        let accepted = Uuid::new_v4();
+        reservations.request(accepted, expires, sender);
+        assert_eq!(
+            reservations.answer(accepted, ReservationStatus::Accepted, None),
+            Ok(true)
+        );
+        assert_eq!(receiver.try_recv(), Ok(Ok(())));
+        assert_eq!(reservations.held(), 1);
+        assert_eq!(reservations.next_expiry(), Some(expires));
+
+        let (sender, mut receiver) = oneshot::channel();
+        let rejected = Uuid::new_v4();
+        reservations.request(rejected, expires, sender);
+        let reason = Some(String::from("match starting"));
+        assert_eq!(
+            reservations.answer(rejected, ReservationStatus::Rejected, reason),
+            Ok(false)
+        );
+        assert_eq!(receiver.try_recv(), Ok(Err(String::from("match starting"))));
+
+        let (sender, receiver) = oneshot::channel();
+        let abandoned = Uuid::new_v4();
+        reservations.request(abandoned, expires, sender);
+        drop(receiver);
+        assert_eq!(
+            reservations.answer(abandoned, ReservationStatus::Accepted, None),
+            Ok(false)
+        );
+        assert_eq!(reservations.held(), 1);
+
+        assert!(reservations
+            .answer(rejected, ReservationStatus::Claimed, None)
+            .is_err());
+        assert_eq!(
+            reservations.answer(accepted, ReservationStatus::Claimed, None),
+            Ok(true)
+        );
+        assert_eq!(reservations.held(), 0);
+    }
+
+    #[test]
+    fn expire_held_slots() {
+        let mut reservations = Reservations::default();
+        let now = Instant::now();
+        let mut receivers = Vec::new();
+        for secs in [10, 20] {
+            let (sender, receiver) = oneshot::channel();
+            receivers.push(receiver);
+            let reservation_id = Uuid::new_v4();
+            reservations.request(reservation_id, now + Duration::from_secs(secs), sender);
+            reservations
+                .answer(reservation_id, ReservationStatus::Accepted, None)
+                .unwrap();
+        }
+        assert!(!reservations.remove_expired(now));
+        assert!(reservations.remove_expired(now + Duration::from_secs(15)));
+        assert_eq!(reservations.held(), 1);
+        assert_eq!(
+            reservations.next_expiry(),
+            Some(now + Duration::from_secs(20))
+        );
+    }
+}
@@ -0,0 +1,49 @@
+use crate::{json_file, GameServer};
+use serde::{Deserialize, Serialize};
+use std::{io, path::Path};
+
+/// The game servers in the list along with the tokens to resume them, saved so the list survives a
+/// restart
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct Snapshot {
+    pub servers: Vec<SavedServer>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SavedServer {
+    pub server: GameServer,
+    pub resume_token: String,
+}
+
+/// Writes the snapshot as compact JSON, as it's saved regularly and only read on startup
+pub async fn save(path: &Path, snapshot: &Snapshot) -> io::Result<()> {
+    json_file::save(path, snapshot, false).await
+}
+
+/// Reads a snapshot saved by [`save`], returning `None` if there isn't one yet
+pub async fn load(path: &Path) -> io::Result<Option<Snapshot>> {
+    json_file::load(path).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server;
+
+    #[tokio::test]
+    async fn save_and_load() {
+        let path = std::env::temp_dir().join(format!("snapshot-{}.json", uuid::Uuid::new_v4()));
+        assert_eq!(load(&path).await.unwrap(), None);
+
+        let server = test_server("Test", 0, false);
+        let snapshot = Snapshot {
+            servers: vec![SavedServer {
+                server,
+                resume_token: String::from("abc"),
+            }],
+        };
+        save(&path, &snapshot).await.unwrap();
+        assert_eq!(load(&path).await.unwrap(), Some(snapshot));
+        tokio::fs::remove_file(&path).await.unwrap();
+    }
+}
@@ -0,0 +1,756 @@
+use crate::{GameServer, ServerEvent};
+use redis::Commands;
+use std::{
+    collections::{BTreeMap, HashSet},
+    sync::{mpsc, Arc, OnceLock, RwLock},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use uuid::Uuid;
+
+// a slow store holds up the writes queued behind it, so give up quickly
+const REDIS_TIMEOUT: Duration = Duration::from_secs(2);
+// how often the instance marks itself as alive and reads the other instances' game servers again
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+// instances which haven't refreshed for this long are assumed to have crashed
+const OWNER_TTL: Duration = Duration::from_secs(15);
+
+#[derive(Debug)]
+pub enum StoreError {
+    Redis(redis::RedisError),
+    Json(serde_json::Error),
+}
+
+impl std::fmt::Display for StoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StoreError::Redis(e) => write!(f, "redis error: {}", e),
+            StoreError::Json(e) => write!(f, "invalid game server in store: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+impl From<redis::RedisError> for StoreError {
+    fn from(error: redis::RedisError) -> Self {
+        StoreError::Redis(error)
+    }
+}
+
+impl From<serde_json::Error> for StoreError {
+    fn from(error: serde_json::Error) -> Self {
+        StoreError::Json(error)
+    }
+}
+
+/// Passes on changes other instances made to a shared store, taking the list's lock while `change`
+/// applies them and returns their events
+pub type Watcher = Box<dyn Fn(&mut dyn FnMut() -> Vec<ServerEvent>) + Send + Sync>;
+
+/// Where the [`ServerList`](crate::ServerList) keeps its game servers
+///
+/// The list serialises its own changes, so implementations only need to make each call safe to run
+/// alongside reads.
+pub trait ServerStore: Send + Sync {
+    /// Adds the server, replacing any with the same id
+    fn add(&self, server: &GameServer) -> Result<(), StoreError>;
+    fn remove(&self, server_id: &Uuid) -> Result<Option<GameServer>, StoreError>;
+    fn get(&self, server_id: &Uuid) -> Result<Option<GameServer>, StoreError>;
+    /// Every server ordered by id, which is registration order
+    fn all(&self) -> Result<Vec<GameServer>, StoreError>;
+    /// Changes the server, returning it after the change
+    fn update(
+        &self,
+        server_id: &Uuid,
+        func: &mut dyn FnMut(&mut GameServer),
+    ) -> Result<Option<GameServer>, StoreError>;
+    fn len(&self) -> Result<usize, StoreError>;
+    fn is_empty(&self) -> Result<bool, StoreError> {
+        Ok(self.len()? == 0)
+    }
+    /// Tells the watcher about servers other instances change, only stores shared between
+    /// instances have any
+    fn watch(&self, _watcher: Watcher) {}
+}
+
+/// Keeps game servers in memory, only visible to this instance of the list
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    servers: RwLock<BTreeMap<Uuid, GameServer>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> MemoryStore {
+        MemoryStore::default()
+    }
+}
+
+impl ServerStore for MemoryStore {
+    fn add(&self, server: &GameServer) -> Result<(), StoreError> {
+        let mut servers = self.servers.write().unwrap();
+        servers.insert(server.id, server.clone());
+        Ok(())
+    }
+    fn remove(&self, server_id: &Uuid) -> Result<Option<GameServer>, StoreError> {
+        let mut servers = self.servers.write().unwrap();
+        Ok(servers.remove(server_id))
+    }
+    fn get(&self, server_id: &Uuid) -> Result<Option<GameServer>, StoreError> {
+        let servers = self.servers.read().unwrap();
+        Ok(servers.get(server_id).cloned())
+    }
+    fn all(&self) -> Result<Vec<GameServer>, StoreError> {
+        let servers = self.servers.read().unwrap();
+        Ok(servers.values().cloned().collect())
+    }
+    fn update(
+        &self,
+        server_id: &Uuid,
+        func: &mut dyn FnMut(&mut GameServer),
+    ) -> Result<Option<GameServer>, StoreError> {
+        let mut servers = self.servers.write().unwrap();
+        Ok(servers.get_mut(server_id).map(|server| {
+            func(server);
+            server.clone()
+        }))
+    }
+    fn len(&self) -> Result<usize, StoreError> {
+        let servers = self.servers.read().unwrap();
+        Ok(servers.len())
+    }
+}
+
+/// Keeps game servers as JSON in Redis so several instances of the list can share them
+///
+/// Each instance only changes the game servers connected to it, which it keeps in memory and writes
+/// to its own hash in Redis on a thread of its own, so calls never wait on Redis. The other
+/// instances' game servers are read from their hashes every second, with `origin` set to the
+/// instance they're connected to. An instance which stops refreshing is ignored after 15 seconds
+/// and its hash expires, so the game servers of crashed instances aren't listed forever.
+pub struct RedisStore {
+    // the game servers connected to this instance
+    local: Servers,
+    // the other instances' game servers as of the last refresh
+    remote: Servers,
+    watcher: Arc<OnceLock<Watcher>>,
+    writes: mpsc::Sender<Write>,
+}
+
+type Servers = Arc<RwLock<BTreeMap<Uuid, GameServer>>>;
+
+enum Write {
+    Add(Uuid, String),
+    Remove(Uuid),
+    // refreshes once everything sent before it has been written
+    Flush(mpsc::Sender<()>),
+}
+
+impl RedisStore {
+    /// Connects lazily to the Redis server at the URL, storing servers under `key` as `owner`
+    ///
+    /// Every instance sharing the key needs its own owner.
+    pub fn new(
+        url: &str,
+        key: impl Into<String>,
+        owner: impl Into<String>,
+    ) -> Result<RedisStore, StoreError> {
+        RedisStore::with_ttl(url, key, owner, OWNER_TTL)
+    }
+    fn with_ttl(
+        url: &str,
+        key: impl Into<String>,
+        owner: impl Into<String>,
+        ttl: Duration,
+    ) -> Result<RedisStore, StoreError> {
+        let (writes, queued) = mpsc::channel();
+        let store = RedisStore {
+            local: Servers::default(),
+            remote: Servers::default(),
+            watcher: Arc::default(),
+            writes,
+        };
+        let key = key.into();
+        let owner = owner.into();
+        let writer = Writer {
+            redis: Redis {
+                client: redis::Client::open(url)?,
+                connection: None,
+            },
+            owners: format!("{}:owners", key),
+            key,
+            owner,
+            ttl,
+            local: store.local.clone(),
+            remote: store.remote.clone(),
+            watcher: store.watcher.clone(),
+            resync: true,
+        };
+        std::thread::spawn(move || writer.run(queued));
+        Ok(store)
+    }
+    /// Waits until every change so far has been written and the other instances' game servers
+    /// have been read again
+    pub fn flush(&self) {
+        let (done, flushed) = mpsc::channel();
+        if self.writes.send(Write::Flush(done)).is_ok() {
+            let _ = flushed.recv();
+        }
+    }
+}
+
+impl ServerStore for RedisStore {
+    fn add(&self, server: &GameServer) -> Result<(), StoreError> {
+        let json = serde_json::to_string(server)?;
+        let mut local = self.local.write().unwrap();
+        local.insert(server.id, server.clone());
+        // sent while locked so changes are written in the order they were made
+        let _ = self.writes.send(Write::Add(server.id, json));
+        Ok(())
+    }
+    fn remove(&self, server_id: &Uuid) -> Result<Option<GameServer>, StoreError> {
+        let mut local = self.local.write().unwrap();
+        let removed = local.remove(server_id);
+        if removed.is_some() {
+            let _ = self.writes.send(Write::Remove(*server_id));
+        }
+        Ok(removed)
+    }
+    fn get(&self, server_id: &Uuid) -> Result<Option<GameServer>, StoreError> {
+        if let Some(server) = self.local.read().unwrap().get(server_id) {
+            return Ok(Some(server.clone()));
+        }
+        Ok(self.remote.read().unwrap().get(server_id).cloned())
+    }
+    fn all(&self) -> Result<Vec<GameServer>, StoreError> {
+        let mut servers = self.remote.read().unwrap().clone();
+        servers.extend(self.local.read().unwrap().clone());
+        Ok(servers.into_values().collect())
+    }
+    fn update(
+        &self,
+        server_id: &Uuid,
+        func: &mut dyn FnMut(&mut GameServer),
+    ) -> Result<Option<GameServer>, StoreError> {
+        let mut local = self.local.write().unwrap();
+        let Some(server) = local.get_mut(server_id) else {
+            return Ok(None);
+        };
+        func(server);
+        let json = serde_json::to_string(server)?;
+        let _ = self.writes.send(Write::Add(*server_id, json));
+        Ok(Some(server.clone()))
+    }
+    fn len(&self) -> Result<usize, StoreError> {
+        let local = self.local.read().unwrap();
+        let remote = self.remote.read().unwrap();
+        let remote_only = remote.keys().filter(|id| !local.contains_key(id)).count();
+        Ok(local.len() + remote_only)
+    }
+    fn watch(&self, watcher: Watcher) {
+        if self.watcher.set(watcher).is_err() {
+            tracing::warn!("the server store is already watched");
+        }
+    }
+}
+
+/// A connection to Redis, reconnected on the next command after a connection error
+struct Redis {
+    client: redis::Client,
+    connection: Option<redis::Connection>,
+}
+
+impl Redis {
+    fn query<T>(
+        &mut self,
+        func: impl FnOnce(&mut redis::Connection) -> redis::RedisResult<T>,
+    ) -> Result<T, StoreError> {
+        let conn = match self.connection.as_mut() {
+            Some(conn) => conn,
+            None => {
+                let conn = self.client.get_connection_with_timeout(REDIS_TIMEOUT)?;
+                conn.set_read_timeout(Some(REDIS_TIMEOUT))?;
+                conn.set_write_timeout(Some(REDIS_TIMEOUT))?;
+                self.connection.insert(conn)
+            }
+        };
+        let result = func(conn);
+        if result
+            .as_ref()
+            .is_err_and(|e| e.is_io_error() || e.is_connection_dropped())
+        {
+            self.connection = None;
+        }
+        Ok(result?)
+    }
+}
+
+/// Writes the instance's game servers to Redis in order and reads the other instances' back
+struct Writer {
+    redis: Redis,
+    key: String,
+    // sorted set of every instance's owner, scored by when it last refreshed
+    owners: String,
+    owner: String,
+    // how long the instance's game servers are kept without it refreshing
+    ttl: Duration,
+    local: Servers,
+    remote: Servers,
+    watcher: Arc<OnceLock<Watcher>>,
+    // set when a write failed, so every game server is written again
+    resync: bool,
+}
+
+impl Writer {
+    fn run(mut self, writes: mpsc::Receiver<Write>) {
+        let mut next_refresh = Instant::now();
+        loop {
+            if Instant::now() >= next_refresh {
+                self.refresh();
+                next_refresh = Instant::now() + REFRESH_INTERVAL;
+            }
+            let timeout = next_refresh.saturating_duration_since(Instant::now());
+            let write = match writes.recv_timeout(timeout) {
+                Ok(write) => write,
+                Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                // the store has been dropped
+                Err(mpsc::RecvTimeoutError::Disconnected) => return,
+            };
+            let hash = self.hash(&self.owner);
+            match write {
+                Write::Add(server_id, json) => {
+                    self.write(|conn| conn.hset::<_, _, _, ()>(hash, server_id.to_string(), json))
+                }
+                Write::Remove(server_id) => {
+                    self.write(|conn| conn.hdel::<_, _, ()>(hash, server_id.to_string()))
+                }
+                Write::Flush(done) => {
+                    self.refresh();
+                    let _ = done.send(());
+                }
+            }
+        }
+    }
+    /// The hash the owner's game servers are kept in
+    fn hash(&self, owner: &str) -> String {
+        format!("{}:servers:{}", self.key, owner)
+    }
+    fn write(&mut self, func: impl FnOnce(&mut redis::Connection) -> redis::RedisResult<()>) {
+        // the next refresh writes everything anyway
+        if self.resync {
+            return;
+        }
+        if let Err(e) = self.redis.query(func) {
+            tracing::error!("failed to write to the server store: {}", e);
+            self.resync = true;
+        }
+    }
+    fn refresh(&mut self) {
+        if self.resync {
+            match self.resync() {
+                Ok(()) => self.resync = false,
+                Err(e) => tracing::error!("failed to write to the server store: {}", e),
+            }
+        }
+        match self.read_remote() {
+            Ok(remote) => self.replace_remote(remote),
+            Err(e) => tracing::error!("failed to read the server store: {}", e),
+        }
+    }
+    /// Swaps in the other instances' game servers as just read, telling the watcher what changed
+    fn replace_remote(&self, mut remote: BTreeMap<Uuid, GameServer>) {
+        // servers connected to this instance win over copies listed from elsewhere
+        let local = self.local.read().unwrap().keys().copied().collect();
+        let mut change = || {
+            let mut previous = self.remote.write().unwrap();
+            let events = remote_events(&previous, &remote, &local);
+            *previous = std::mem::take(&mut remote);
+            events
+        };
+        match self.watcher.get() {
+            Some(watcher) => watcher(&mut change),
+            None => {
+                change();
+            }
+        }
+    }
+    /// Replaces everything in the instance's hash with the game servers it has now
+    fn resync(&mut self) -> Result<(), StoreError> {
+        let servers = self
+            .local
+            .read()
+            .unwrap()
+            .values()
+            .map(|server| Ok((server.id.to_string(), serde_json::to_string(server)?)))
+            .collect::<Result<Vec<_>, StoreError>>()?;
+        let hash = self.hash(&self.owner);
+        let ttl = self.ttl_millis();
+        self.redis.query(|conn| {
+            let mut pipe = redis::pipe();
+            pipe.atomic().del(&hash).ignore();
+            if !servers.is_empty() {
+                pipe.hset_multiple(&hash, &servers)
+                    .ignore()
+                    .pexpire(&hash, ttl)
+                    .ignore();
+            }
+            pipe.query(conn)
+        })
+    }
+    /// Marks the instance as alive and reads the game servers of every other live instance
+    fn read_remote(&mut self) -> Result<BTreeMap<Uuid, GameServer>, StoreError> {
+        let (owners_key, owner) = (self.owners.clone(), self.owner.clone());
+        let (hash, ttl) = (self.hash(&self.owner), self.ttl_millis());
+        let now = unix_millis();
+        let alive_since = now.saturating_sub(self.ttl.as_millis().try_into().unwrap_or(u64::MAX));
+        let owners: Vec<String> = self.redis.query(|conn| {
+            conn.zadd::<_, _, _, ()>(&owners_key, &owner, now)?;
+            conn.pexpire::<_, ()>(&hash, ttl)?;
+            conn.zrembyscore::<_, _, _, ()>(&owners_key, "-inf", alive_since.saturating_sub(1))?;
+            conn.zrangebyscore(&owners_key, alive_since, "+inf")
+        })?;
+        let mut remote = BTreeMap::new();
+        for owner in owners.iter().filter(|owner| **owner != self.owner) {
+            let hash = self.hash(owner);
+            let values: Vec<String> = self.redis.query(|conn| conn.hvals(hash))?;
+            for json in values {
+                let mut server = serde_json::from_str::<GameServer>(&json)?;
+                // servers the instance listed from a federation peer keep the peer as their origin
+                server.origin.get_or_insert_with(|| owner.clone());
+                remote.insert(server.id, server);
+            }
+        }
+        Ok(remote)
+    }
+    fn ttl_millis(&self) -> usize {
+        self.ttl.as_millis().try_into().unwrap_or(usize::MAX)
+    }
+}
+
+/// The events for subscribers to see the other instances' game servers change from `previous`
+fn remote_events(
+    previous: &BTreeMap<Uuid, GameServer>,
+    current: &BTreeMap<Uuid, GameServer>,
+    local: &HashSet<Uuid>,
+) -> Vec<ServerEvent> {
+    let mut events = Vec::new();
+    for (server_id, server) in current.iter().filter(|(id, _)| !local.contains(id)) {
+        match previous.get(server_id) {
+            None => events.push(ServerEvent::Added {
+                server: server.clone(),
+            }),
+            Some(previous) if !previous.same_listing(server) => events.push(ServerEvent::Updated {
+                server: server.clone(),
+            }),
+            Some(_) => {}
+        }
+    }
+    for server_id in previous.keys() {
+        if !current.contains_key(server_id) && !local.contains(server_id) {
+            events.push(ServerEvent::Removed { id: *server_id });
+        }
+    }
+    events
+}
+
+fn unix_millis() -> u64 {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH);
+    now.map_or(0, |since| since.as_millis().try_into().unwrap_or(u64::MAX))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_server, ServerList};
+    use std::{
+        collections::HashMap,
+        io::{BufRead, BufReader, Write},
+        net::{TcpListener, TcpStream},
+        sync::Mutex,
+    };
+
+    #[derive(Default)]
+    struct FakeRedis {
+        hashes: HashMap<String, BTreeMap<String, String>>,
+        sorted_sets: HashMap<String, HashMap<String, f64>>,
+    }
+
+    // stands in for Redis, only understanding the commands the store uses
+    fn fake_redis() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("redis://{}/", listener.local_addr().unwrap());
+        let redis = Arc::new(Mutex::new(FakeRedis::default()));
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let redis = redis.clone();
+                std::thread::spawn(move || serve_fake_redis(stream.unwrap(), &redis));
+            }
+        });
+        url
+    }
+
+    fn serve_fake_redis(stream: TcpStream, redis: &Mutex<FakeRedis>) {
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut stream = stream;
+        let read_line = |reader: &mut BufReader<TcpStream>| {
+            let mut line = String::new();
+            reader.read_line(&mut line).ok().filter(|n| *n > 0)?;
+            Some(line.trim_end().to_string())
+        };
+        // commands queued since MULTI
+        let mut transaction: Option<Vec<Vec<String>>> = None;
+        while let Some(header) = read_line(&mut reader) {
+            let count: usize = header[1..].parse().unwrap();
+            let args = (0..count)
+                .map(|_| {
+                    read_line(&mut reader);
+                    read_line(&mut reader).unwrap()
+                })
+                .collect::<Vec<_>>();
+            let reply = match (args[0].to_ascii_uppercase().as_str(), &mut transaction) {
+                ("MULTI", _) => {
+                    transaction = Some(Vec::new());
+                    String::from("+OK\r\n")
+                }
+                ("EXEC", Some(_)) => {
+                    let queued = transaction.take().unwrap();
+                    let mut redis = redis.lock().unwrap();
+                    let replies = queued.iter().map(|args| fake_command(&mut redis, args));
+                    format!("*{}\r\n{}", queued.len(), replies.collect::<String>())
+                }
+                (_, Some(queued)) => {
+                    queued.push(args);
+                    String::from("+QUEUED\r\n")
+                }
+                (_, None) => fake_command(&mut redis.lock().unwrap(), &args),
+            };
+            stream.write_all(reply.as_bytes()).unwrap();
+        }
+    }
+
+    fn fake_command(redis: &mut FakeRedis, args: &[String]) -> String {
+        let bulk = |value: &String| format!("${}\r\n{}\r\n", value.len(), value);
+        let score = |arg: &String| arg.parse::<f64>().unwrap();
+        match args[0].to_ascii_uppercase().as_str() {
+            "DEL" => {
+                let hash = redis.hashes.remove(&args[1]).is_some();
+                let sorted_set = redis.sorted_sets.remove(&args[1]).is_some();
+                format!(":{}\r\n", u8::from(hash || sorted_set))
+            }
+            command @ ("HSET" | "HMSET") => {
+                let hash = redis.hashes.entry(args[1].clone()).or_default();
+                let added = args[2..]
+                    .chunks(2)
+                    .filter(|pair| hash.insert(pair[0].clone(), pair[1].clone()).is_none())
+                    .count();
+                match command {
+                    "HSET" => format!(":{}\r\n", added),
+                    _ => String::from("+OK\r\n"),
+                }
+            }
+            "HDEL" => {
+                let hash = redis.hashes.entry(args[1].clone()).or_default();
+                format!(":{}\r\n", u8::from(hash.remove(&args[2]).is_some()))
+            }
+            "HVALS" => {
+                let hash = redis.hashes.entry(args[1].clone()).or_default();
+                let values = hash.values().map(bulk);
+                format!("*{}\r\n{}", hash.len(), values.collect::<String>())
+            }
+            "ZADD" => {
+                let sorted_set = redis.sorted_sets.entry(args[1].clone()).or_default();
+                let added = sorted_set
+                    .insert(args[3].clone(), score(&args[2]))
+                    .is_none();
+                format!(":{}\r\n", u8::from(added))
+            }
+            "PEXPIRE" => String::from(":1\r\n"),
+            "ZREMRANGEBYSCORE" => {
+                let sorted_set = redis.sorted_sets.entry(args[1].clone()).or_default();
+                let (min, max) = (score(&args[2]), score(&args[3]));
+                let count = sorted_set.len();
+                sorted_set.retain(|_, score| !(min..=max).contains(score));
+                format!(":{}\r\n", count - sorted_set.len())
+            }
+            "ZRANGEBYSCORE" => {
+                let sorted_set = redis.sorted_sets.entry(args[1].clone()).or_default();
+                let (min, max) = (score(&args[2]), score(&args[3]));
+                let members = sorted_set
+                    .iter()
+                    .filter(|(_, score)| (min..=max).contains(*score))
+                    .map(|(member, _)| bulk(member))
+                    .collect::<Vec<_>>();
+                format!("*{}\r\n{}", members.len(), members.concat())
+            }
+            command => format!("-ERR unknown command {}\r\n", command),
+        }
+    }
+
+    // servers are added to stores with the id the list gives them
+    fn stored_server(name: &str) -> GameServer {
+        GameServer {
+            id: Uuid::now_v7(),
+            ..test_server(name, 0, false)
+        }
+    }
+
+    fn check_store(store: &dyn ServerStore) {
+        let first = stored_server("First");
+        let second = stored_server("Second");
+        store.add(&second).unwrap();
+        store.add(&first).unwrap();
+        assert_eq!(store.len().unwrap(), 2);
+        assert_eq!(store.get(&first.id).unwrap(), Some(first.clone()));
+        assert_eq!(store.all().unwrap(), vec![first.clone(), second.clone()]);
+
+        let updated = store
+            .update(&first.id, &mut |server| server.players = 5)
+            .unwrap();
+        assert_eq!(updated.map(|server| server.players), Some(5));
+        assert_eq!(store.get(&first.id).unwrap().unwrap().players, 5);
+        assert_eq!(store.update(&Uuid::nil(), &mut |_| {}).unwrap(), None);
+
+        assert_eq!(store.remove(&second.id).unwrap(), Some(second.clone()));
+        assert_eq!(store.remove(&second.id).unwrap(), None);
+        assert_eq!(store.len().unwrap(), 1);
+        assert!(!store.is_empty().unwrap());
+    }
+
+    #[test]
+    fn memory_store() {
+        check_store(&MemoryStore::new());
+    }
+
+    #[test]
+    fn redis_store() {
+        check_store(&RedisStore::new(&fake_redis(), "servers", "eu-1").unwrap());
+    }
+
+    #[test]
+    fn redis_store_shared() {
+        let url = fake_redis();
+        let first = RedisStore::new(&url, "servers", "eu-1").unwrap();
+        let second = RedisStore::new(&url, "servers", "eu-2").unwrap();
+        let server = stored_server("First");
+        first.add(&server).unwrap();
+        first.flush();
+        second.flush();
+        let listed = GameServer {
+            origin: Some(String::from("eu-1")),
+            ..server.clone()
+        };
+        assert_eq!(second.get(&server.id).unwrap(), Some(listed));
+        assert_eq!(second.len().unwrap(), 1);
+        // only the instance the game server is connected to changes it
+        assert_eq!(second.update(&server.id, &mut |_| {}).unwrap(), None);
+        assert_eq!(second.remove(&server.id).unwrap(), None);
+
+        first.remove(&server.id).unwrap();
+        first.flush();
+        second.flush();
+        assert!(second.is_empty().unwrap());
+    }
+
+    #[test]
+    fn redis_store_notifies_subscribers() {
+        let url = fake_redis();
+        let other = RedisStore::new(&url, "servers", "eu-1").unwrap();
+        let store = Arc::new(RedisStore::new(&url, "servers", "eu-2").unwrap());
+        let server_list = ServerList::with_store(store.clone());
+        let (servers, mut events) = server_list.subscribe();
+        assert!(servers.is_empty());
+
+        let mut server = stored_server("Remote");
+        other.add(&server).unwrap();
+        other.flush();
+        store.flush();
+        assert!(matches!(events.try_recv(), Ok(ServerEvent::Added { .. })));
+
+        server.players = 3;
+        other.add(&server).unwrap();
+        other.flush();
+        store.flush();
+        match events.try_recv() {
+            Ok(ServerEvent::Updated { server }) => assert_eq!(server.players, 3),
+            event => panic!("expected an update, got {:?}", event),
+        }
+
+        // nothing to tell when only last_seen changed
+        server.last_seen += 1;
+        other.add(&server).unwrap();
+        other.flush();
+        store.flush();
+        assert!(events.try_recv().is_err());
+
+        other.remove(&server.id).unwrap();
+        other.flush();
+        store.flush();
+        assert!(matches!(
+            events.try_recv(),
+            Ok(ServerEvent::Removed { id }) if id == server.id
+        ));
+    }
+
+    #[test]
+    fn redis_store_expires_crashed_instances() {
+        let url = fake_redis();
+        let ttl = Duration::from_millis(200);
+        let crashed = RedisStore::with_ttl(&url, "servers", "eu-1", ttl).unwrap();
+        let store = Arc::new(RedisStore::with_ttl(&url, "servers", "eu-2", ttl).unwrap());
+        crashed.add(&stored_server("Crashed")).unwrap();
+        crashed.flush();
+        let server_list = ServerList::with_store(store.clone());
+        server_list.clone().add(stored_server("Local"));
+        store.flush();
+        assert_eq!(server_list.all().len(), 2);
+        let local = server_list.local();
+        assert_eq!(local.len(), 1);
+        assert_eq!(local[0].name, "Local");
+
+        // stops refreshing
+        drop(crashed);
+        std::thread::sleep(ttl * 2);
+        store.flush();
+        assert_eq!(server_list.all(), local);
+    }
+
+    #[test]
+    fn redis_store_interleaved_updates() {
+        let url = fake_redis();
+        let store = Arc::new(RedisStore::new(&url, "servers", "eu-1").unwrap());
+        let server = stored_server("First");
+        store.add(&server).unwrap();
+        let updaters = (0..2)
+            .map(|_| {
+                let store = store.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..100 {
+                        store
+                            .update(&server.id, &mut |server| server.players += 1)
+                            .unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for updater in updaters {
+            updater.join().unwrap();
+        }
+        store.flush();
+        let other = RedisStore::new(&url, "servers", "eu-2").unwrap();
+        other.flush();
+        assert_eq!(other.get(&server.id).unwrap().unwrap().players, 200);
+    }
+
+    #[test]
+    fn redis_store_unavailable() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("redis://{}/", listener.local_addr().unwrap());
+        drop(listener);
+        // the instance keeps its own game servers until Redis is back, another test's fake Redis
+        // may take the port so stay out of its way
+        let store = RedisStore::new(&url, "unavailable", "eu-1").unwrap();
+        let server = stored_server("First");
+        store.add(&server).unwrap();
+        store.flush();
+        assert_eq!(store.all().unwrap(), vec![server]);
+    }
+}
@@ -0,0 +1,114 @@
+use semver::Version;
+use serde::{Deserialize, Deserializer};
+
+/// Which game servers a client running a given version can join
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum VersionRule {
+    /// Only servers running the same version
+    #[default]
+    Exact,
+    /// Servers in the client's semver range, the same major version or the same minor version
+    /// before 1.0
+    Semver,
+    /// Servers running the client's version or newer
+    Minimum,
+}
+
+impl VersionRule {
+    /// Returns whether a client can join a server, servers without a version are never compatible
+    pub fn compatible(&self, client: &str, server: Option<&str>) -> bool {
+        let Some(server) = server else {
+            return false;
+        };
+        let parsed = || Some((parse_version(client)?, parse_version(server)?));
+        match self {
+            VersionRule::Exact => client.trim() == server.trim(),
+            VersionRule::Semver => {
+                parsed().is_some_and(|(client, server)| match (client.major, client.minor) {
+                    (0, 0) => {
+                        server.major == 0 && server.minor == 0 && server.patch == client.patch
+                    }
+                    (0, minor) => server.major == 0 && server.minor == minor,
+                    (major, _) => server.major == major,
+                })
+            }
+            VersionRule::Minimum => parsed().is_some_and(|(client, server)| server >= client),
+        }
+    }
+}
+
+/// Parses a semver version, filling in a missing minor or patch version so `1.2` is `1.2.0`, and
+/// allowing a leading `v`
+pub fn parse_version(version: &str) -> Option<Version> {
+    let version = version.trim();
+    let version = version.strip_prefix('v').unwrap_or(version);
+    // the pre-release and build metadata follow the patch version
+    let core_len = version.find(['-', '+']).unwrap_or(version.len());
+    let (core, rest) = version.split_at(core_len);
+    let padding = match core.split('.').count() {
+        1 => ".0.0",
+        2 => ".0",
+        _ => "",
+    };
+    Version::parse(&format!("{}{}{}", core, padding, rest)).ok()
+}
+
+/// Deserializes an optional version with [`parse_version`]
+pub fn deserialize_version<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Version>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .filter(|version| !version.trim().is_empty())
+        .map(|version| {
+            parse_version(&version)
+                .ok_or_else(|| format!("{:?} is not a valid version", version))
+                .map_err(serde::de::Error::custom)
+        })
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_partial_versions() {
+        assert_eq!(parse_version("1.2"), Some(Version::new(1, 2, 0)));
+        assert_eq!(parse_version("v3"), Some(Version::new(3, 0, 0)));
+        assert_eq!(
+            parse_version("1.2-beta.1"),
+            Version::parse("1.2.0-beta.1").ok()
+        );
+        assert_eq!(parse_version("not a version"), None);
+    }
+
+    #[test]
+    fn exact_rule() {
+        let rule = VersionRule::Exact;
+        assert!(rule.compatible("1.2.0", Some("1.2.0")));
+        assert!(!rule.compatible("1.2.0", Some("1.2.1")));
+        assert!(!rule.compatible("1.2.0", None));
+    }
+
+    #[test]
+    fn semver_rule() {
+        let rule = VersionRule::Semver;
+        assert!(rule.compatible("1.2.0", Some("1.0.3")));
+        assert!(rule.compatible("1.2.0", Some("1.9")));
+        assert!(!rule.compatible("1.2.0", Some("2.0.0")));
+        assert!(rule.compatible("0.3.1", Some("0.3.0")));
+        assert!(!rule.compatible("0.3.1", Some("0.4.0")));
+        assert!(!rule.compatible("0.0.1", Some("0.0.2")));
+        assert!(!rule.compatible("1.2.0", Some("custom build")));
+    }
+
+    #[test]
+    fn minimum_rule() {
+        let rule = VersionRule::Minimum;
+        assert!(rule.compatible("1.2.0", Some("1.2.0")));
+        assert!(rule.compatible("1.2.0", Some("2.0")));
+        assert!(!rule.compatible("1.2.0", Some("1.1.9")));
+        assert!(!rule.compatible("1.2.0", Some("1.2.0-beta")));
+    }
+}